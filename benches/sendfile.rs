@@ -0,0 +1,134 @@
+//! Throughput of a large static file sent with sendfile(2) and through a buffer
+//!
+//! ```text
+//! cargo bench --bench sendfile
+//! ```
+//!
+//! Each mode serves the same file from a fresh server and fetches it a few
+//! times over loopback. The file is sparse, so it comes from the page cache
+//! rather than the disk. CPU time covers the whole process, client included,
+//! so the difference between the two is what the server saved.
+
+use std::{
+    net::SocketAddr,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use webserver::{Server, StaticFiles};
+
+const FILE_SIZE: u64 = 1024 * 1024 * 1024;
+const REQUESTS: usize = 8;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let root = std::env::temp_dir().join(format!("webserver-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&root)?;
+    std::fs::File::create(root.join("big.bin"))?.set_len(FILE_SIZE)?;
+
+    let result = async {
+        for sendfile in [false, true] {
+            run(&root, sendfile).await?;
+        }
+        anyhow::Ok(())
+    }
+    .await;
+
+    std::fs::remove_dir_all(&root)?;
+    result
+}
+
+async fn run(root: &Path, sendfile: bool) -> anyhow::Result<()> {
+    let server = Server::builder()
+        .bind("127.0.0.1:0")
+        .handler(StaticFiles::new(root).sendfile(sendfile))
+        .build()
+        .await?;
+    let addr = server.local_addrs()?[0];
+    let handle = server.handle();
+    let serving = tokio::spawn(server.serve());
+
+    // one request first so the file is in the page cache
+    fetch(addr).await?;
+
+    let cpu = cpu_time();
+    let start = Instant::now();
+    let mut total = 0;
+    for _ in 0..REQUESTS {
+        total += fetch(addr).await?;
+    }
+    let elapsed = start.elapsed();
+    let cpu = cpu_time().saturating_sub(cpu);
+
+    let gib = total as f64 / (1024.0 * 1024.0 * 1024.0);
+    println!(
+        "{:<9} {:>6.2} GiB in {:>6.2?}  {:>6.2} GiB/s  {:>6.2?} CPU  {:>6.2?} CPU/GiB",
+        if sendfile { "sendfile" } else { "buffered" },
+        gib,
+        elapsed,
+        gib / elapsed.as_secs_f64(),
+        cpu,
+        cpu.div_f64(gib),
+    );
+
+    handle.shutdown();
+    serving.await??;
+    Ok(())
+}
+
+/// Fetches the file, returning how many bytes of body came back
+async fn fetch(addr: SocketAddr) -> anyhow::Result<u64> {
+    let mut stream = TcpStream::connect(addr).await?;
+    stream
+        .write_all(b"GET /big.bin HTTP/1.1\r\nHost: bench\r\nConnection: close\r\n\r\n")
+        .await?;
+
+    let mut buf = vec![0; 1024 * 256];
+    let mut head = Vec::new();
+    let mut body = None;
+    loop {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        match body {
+            Some(ref mut body) => *body += n as u64,
+            None => {
+                head.extend_from_slice(&buf[..n]);
+                if let Some(end) = head.windows(4).position(|w| w == b"\r\n\r\n") {
+                    body = Some((head.len() - end - 4) as u64);
+                }
+            }
+        }
+    }
+
+    let body = body.unwrap_or_default();
+    if body != FILE_SIZE {
+        anyhow::bail!("got {body} bytes of body, expected {FILE_SIZE}");
+    }
+    Ok(body)
+}
+
+/// User and system time used by the process so far
+#[cfg(unix)]
+fn cpu_time() -> Duration {
+    // SAFETY: getrusage only writes to the struct it's given
+    let usage = unsafe {
+        let mut usage = std::mem::zeroed::<libc::rusage>();
+        libc::getrusage(libc::RUSAGE_SELF, &mut usage);
+        usage
+    };
+    let time = |t: libc::timeval| {
+        Duration::from_secs(t.tv_sec as u64) + Duration::from_micros(t.tv_usec as u64)
+    };
+    time(usage.ru_utime) + time(usage.ru_stime)
+}
+
+#[cfg(not(unix))]
+fn cpu_time() -> Duration {
+    Duration::ZERO
+}
@@ -0,0 +1,228 @@
+use std::{fmt::Write, time::SystemTime};
+
+use crate::{date, uri};
+
+/// A file or directory in a listing
+#[derive(Debug)]
+pub struct Entry {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, strum::Display, strum::EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum SortKey {
+    Name,
+    Size,
+    Modified,
+}
+
+/// How a listing is ordered, from the `sort` and `order` query parameters
+///
+/// e.g. `?sort=size&order=desc`. Directories always come first.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Sort {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl Sort {
+    /// Anything missing or not understood falls back to by name, ascending
+    pub fn from_query(query: Option<&str>) -> Self {
+        let mut sort = Sort {
+            key: SortKey::Name,
+            descending: false,
+        };
+
+        for (name, value) in query.into_iter().flat_map(uri::query_pairs) {
+            match name.as_str() {
+                "sort" => sort.key = value.parse().unwrap_or(SortKey::Name),
+                "order" => sort.descending = value == "desc",
+                _ => (),
+            }
+        }
+        sort
+    }
+
+    pub fn apply(&self, entries: &mut [Entry]) {
+        entries.sort_by(|a, b| {
+            let order = match self.key {
+                SortKey::Name => a.name.cmp(&b.name),
+                SortKey::Size => a.size.cmp(&b.size).then_with(|| a.name.cmp(&b.name)),
+                SortKey::Modified => a
+                    .modified
+                    .cmp(&b.modified)
+                    .then_with(|| a.name.cmp(&b.name)),
+            };
+            let order = if self.descending {
+                order.reverse()
+            } else {
+                order
+            };
+            b.is_dir.cmp(&a.is_dir).then(order)
+        });
+    }
+
+    /// The query for a column heading, which flips the order if it's already sorted by it
+    fn link(&self, key: SortKey) -> String {
+        let order = if self.key == key && !self.descending {
+            "desc"
+        } else {
+            "asc"
+        };
+        format!("?sort={key}&order={order}")
+    }
+}
+
+/// Whether the Accept header ranks JSON above HTML (RFC 9110 section 12.5.1)
+///
+/// Browsers send `*/*` at a lower quality than text/html, so only clients
+/// that ask for JSON get it.
+pub fn prefers_json(accept: Option<&str>) -> bool {
+    accept.is_some_and(|accept| quality(accept, "application/json") > quality(accept, "text/html"))
+}
+
+/// The q-value given to a media type by the most specific range that matches it
+fn quality(accept: &str, media_type: &str) -> f32 {
+    let (kind, _) = media_type.split_once('/').unwrap_or((media_type, ""));
+
+    let mut best = (0, 0.0);
+    for range in accept.split(',') {
+        let mut params = range.split(';').map(str::trim);
+        let range = params.next().unwrap_or_default();
+
+        let specificity = if range.eq_ignore_ascii_case(media_type) {
+            3
+        } else if range
+            .strip_suffix("/*")
+            .is_some_and(|range| range.eq_ignore_ascii_case(kind))
+        {
+            2
+        } else if range == "*/*" {
+            1
+        } else {
+            continue;
+        };
+
+        let q = params
+            .filter_map(|param| param.split_once('='))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+            .and_then(|(_, q)| q.trim().parse().ok())
+            .unwrap_or(1.0);
+        if specificity > best.0 {
+            best = (specificity, q);
+        }
+    }
+    best.1
+}
+
+/// An HTML page listing the entries, with column headings that sort it
+///
+/// `path` is the decoded request path, `parent` adds a link up a level
+/// for anything but the top of the listing.
+pub fn html(path: &str, entries: &[Entry], sort: Sort, parent: bool) -> String {
+    let title = format!("Index of {}", escape_html(path));
+    let mut page = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n</head>\n<body>\n<h1>{title}</h1>\n<table>\n"
+    );
+
+    let _ = writeln!(
+        page,
+        "<tr><th><a href=\"{}\">Name</a></th><th><a href=\"{}\">Size</a></th><th><a href=\"{}\">Modified</a></th></tr>",
+        escape_html(&sort.link(SortKey::Name)),
+        escape_html(&sort.link(SortKey::Size)),
+        escape_html(&sort.link(SortKey::Modified)),
+    );
+    if parent {
+        page.push_str("<tr><td><a href=\"../\">../</a></td><td></td><td></td></tr>\n");
+    }
+
+    for entry in entries {
+        let slash = if entry.is_dir { "/" } else { "" };
+        let size = if entry.is_dir {
+            "-".to_string()
+        } else {
+            entry.size.to_string()
+        };
+        let modified = entry.modified.map(date::format_rfc3339).unwrap_or_default();
+        let _ = writeln!(
+            page,
+            "<tr><td><a href=\"{}{slash}\">{}{slash}</a></td><td>{size}</td><td>{modified}</td></tr>",
+            uri::percent_encode(&entry.name),
+            escape_html(&entry.name),
+        );
+    }
+
+    page.push_str("</table>\n</body>\n</html>\n");
+    page
+}
+
+/// The listing as JSON, for tools rather than people
+///
+/// ```json
+/// {"path":"/files/","entries":[{"name":"a.txt","type":"file","size":12,"modified":"2025-01-01T00:00:00Z"}]}
+/// ```
+///
+/// Directories have a null size.
+pub fn json(path: &str, entries: &[Entry]) -> String {
+    let mut out = format!("{{\"path\":{},\"entries\":[", escape_json(path));
+    for (i, entry) in entries.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+
+        let (kind, size) = if entry.is_dir {
+            ("directory", "null".to_string())
+        } else {
+            ("file", entry.size.to_string())
+        };
+        let modified = entry.modified.map_or("null".to_string(), |modified| {
+            escape_json(&date::format_rfc3339(modified))
+        });
+        let _ = write!(
+            out,
+            "{{\"name\":{},\"type\":\"{kind}\",\"size\":{size},\"modified\":{modified}}}",
+            escape_json(&entry.name)
+        );
+    }
+    out.push_str("]}\n");
+    out
+}
+
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A JSON string literal, quotes included
+fn escape_json(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
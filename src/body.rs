@@ -0,0 +1,450 @@
+use tokio::{
+    fs::File,
+    io::AsyncRead,
+    sync::{mpsc, oneshot},
+};
+
+use crate::{Headers, ParseError, Version};
+
+/// How many chunks can be queued up ahead of the connection
+const STREAM_BUFFER: usize = 16;
+
+/// Response body
+#[derive(Default)]
+pub enum Body {
+    #[default]
+    Empty,
+
+    Bytes(Vec<u8>),
+
+    /// Streamed from a reader, with its length if known up front
+    Reader {
+        reader: Box<dyn AsyncRead + Send + Unpin>,
+        length: Option<u64>,
+    },
+
+    /// `length` bytes of a file from `offset`, which can go straight from
+    /// the file to the socket without being copied through us
+    File {
+        file: File,
+        offset: u64,
+        length: u64,
+    },
+
+    /// Chunks sent from elsewhere through a BodySender
+    Stream(mpsc::Receiver<Frame>),
+}
+
+/// A piece of a streamed body
+#[derive(Debug)]
+pub enum Frame {
+    Data(Vec<u8>),
+
+    /// Trailer fields, which end the body
+    Trailers(Headers),
+}
+
+/// Sending half of a streamed body
+///
+/// The body ends when the sender is dropped or trailers are sent.
+#[derive(Debug)]
+pub struct BodySender {
+    tx: mpsc::Sender<Frame>,
+}
+
+impl BodySender {
+    /// Sends a chunk, waiting if the connection has fallen behind
+    ///
+    /// Fails if the response is no longer being written, e.g. the client went away.
+    pub async fn send(&self, chunk: impl Into<Vec<u8>>) -> anyhow::Result<()> {
+        self.tx
+            .send(Frame::Data(chunk.into()))
+            .await
+            .map_err(|_| anyhow::anyhow!("response body closed"))
+    }
+
+    /// Ends the body with trailer fields
+    pub async fn send_trailers(self, trailers: Headers) -> anyhow::Result<()> {
+        self.tx
+            .send(Frame::Trailers(trailers))
+            .await
+            .map_err(|_| anyhow::anyhow!("response body closed"))
+    }
+}
+
+impl Body {
+    pub fn from_reader(
+        reader: impl AsyncRead + Send + Unpin + 'static,
+        length: Option<u64>,
+    ) -> Self {
+        Body::Reader {
+            reader: Box::new(reader),
+            length,
+        }
+    }
+
+    /// Sends part of a file, with sendfile(2) where the connection allows it
+    ///
+    /// The file's position doesn't matter, the body always starts at `offset`.
+    pub fn from_file(file: File, offset: u64, length: u64) -> Self {
+        Body::File {
+            file,
+            offset,
+            length,
+        }
+    }
+
+    /// Creates a body that's streamed from the returned sender
+    pub fn channel() -> (BodySender, Self) {
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        (BodySender { tx }, Body::Stream(rx))
+    }
+
+    /// The body length, if it's known before it's written
+    pub fn length(&self) -> Option<u64> {
+        match self {
+            Body::Empty => Some(0),
+            Body::Bytes(bytes) => Some(bytes.len() as u64),
+            Body::Reader { length, .. } => *length,
+            Body::File { length, .. } => Some(*length),
+            Body::Stream(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Debug for Body {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Body::Empty => write!(f, "Empty"),
+            Body::Bytes(bytes) => write!(f, "Bytes({})", bytes.len()),
+            Body::Reader { length, .. } => write!(f, "Reader({length:?})"),
+            Body::File { offset, length, .. } => write!(f, "File({offset}, {length})"),
+            Body::Stream(_) => write!(f, "Stream"),
+        }
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Self {
+        Body::Bytes(bytes)
+    }
+}
+
+impl From<String> for Body {
+    fn from(s: String) -> Self {
+        Body::Bytes(s.into_bytes())
+    }
+}
+
+impl From<&str> for Body {
+    fn from(s: &str) -> Self {
+        Body::Bytes(s.as_bytes().to_vec())
+    }
+}
+
+/// How the request body is delimited (RFC 9112 section 6.3)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BodyFraming {
+    None,
+    Length(u64),
+    Chunked,
+}
+
+impl BodyFraming {
+    /// Works out the framing from the request headers
+    ///
+    /// Anything ambiguous is rejected outright since a proxy in front of us
+    /// may have framed it differently (request smuggling).
+    pub fn from_headers(
+        headers: &Headers,
+        version: Version,
+        max_body_size: u64,
+    ) -> Result<Self, ParseError> {
+        if headers.contains("Transfer-Encoding") {
+            if headers.contains("Content-Length") {
+                return Err(ParseError::BadRequest(
+                    "both content-length and transfer-encoding",
+                ));
+            }
+
+            // HTTP/1.0 doesn't have transfer codings
+            if version == Version::Http10 {
+                return Err(ParseError::BadRequest("transfer-encoding in HTTP/1.0"));
+            }
+
+            let codings: Vec<&str> = headers
+                .get_all("Transfer-Encoding")
+                .flat_map(|v| v.split(','))
+                .map(str::trim)
+                .filter(|c| !c.is_empty())
+                .collect();
+
+            // chunked has to be last or there's no way to find the end
+            return match codings.as_slice() {
+                [c] if c.eq_ignore_ascii_case("chunked") => Ok(BodyFraming::Chunked),
+                [.., last] if last.eq_ignore_ascii_case("chunked") => {
+                    Err(ParseError::UnsupportedTransferCoding)
+                }
+                _ => Err(ParseError::BadRequest("transfer-encoding not chunked")),
+            };
+        }
+
+        let mut length = None;
+        for value in headers
+            .get_all("Content-Length")
+            .flat_map(|v| v.split(','))
+            .map(str::trim)
+        {
+            if value.is_empty() || !value.bytes().all(|c| c.is_ascii_digit()) {
+                return Err(ParseError::BadRequest("invalid content-length"));
+            }
+
+            let value: u64 = value
+                .parse()
+                .map_err(|_| ParseError::BadRequest("invalid content-length"))?;
+
+            // repeated values are only allowed if they all agree
+            if length.is_some_and(|length| length != value) {
+                return Err(ParseError::BadRequest("conflicting content-length"));
+            }
+            length = Some(value);
+        }
+
+        match length {
+            Some(length) if length > max_body_size => Err(ParseError::ContentTooLarge),
+            Some(0) | None => Ok(BodyFraming::None),
+            Some(length) => Ok(BodyFraming::Length(length)),
+        }
+    }
+}
+
+/// Request body, read off the connection as the handler asks for it
+///
+/// If the client sent Expect: 100-continue, asking for the first chunk is
+/// what sends it the 100 Continue. A handler that answers without touching
+/// the body never gets sent it.
+pub struct RequestBody {
+    rx: Option<mpsc::Receiver<Result<Frame, ParseError>>>,
+    length: Option<u64>,
+    trailers: Option<Headers>,
+    continue_tx: Option<oneshot::Sender<()>>,
+}
+
+impl RequestBody {
+    pub fn empty() -> Self {
+        Self {
+            rx: None,
+            length: Some(0),
+            trailers: None,
+            continue_tx: None,
+        }
+    }
+
+    /// Creates a body that's fed from the returned sender
+    pub fn channel(length: Option<u64>) -> (mpsc::Sender<Result<Frame, ParseError>>, Self) {
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        let body = Self {
+            rx: Some(rx),
+            length,
+            trailers: None,
+            continue_tx: None,
+        };
+        (tx, body)
+    }
+
+    /// Returns a receiver that fires the first time the body is read
+    ///
+    /// It's dropped without firing if the body is dropped unread.
+    pub fn on_first_read(&mut self) -> oneshot::Receiver<()> {
+        let (tx, rx) = oneshot::channel();
+        self.continue_tx = Some(tx);
+        rx
+    }
+
+    /// The Content-Length, if the body isn't chunked
+    pub fn length(&self) -> Option<u64> {
+        self.length
+    }
+
+    /// Returns the next chunk of the body, or None once it's all been read
+    pub async fn chunk(&mut self) -> Result<Option<Vec<u8>>, ParseError> {
+        if let Some(continue_tx) = self.continue_tx.take() {
+            let _ = continue_tx.send(());
+        }
+
+        let Some(rx) = &mut self.rx else {
+            return Ok(None);
+        };
+
+        match rx.recv().await {
+            Some(Ok(Frame::Data(data))) => Ok(Some(data)),
+            Some(Ok(Frame::Trailers(trailers))) => {
+                self.trailers = Some(trailers);
+                self.rx = None;
+                Ok(None)
+            }
+            Some(Err(e)) => {
+                self.rx = None;
+                Err(e)
+            }
+            None => {
+                self.rx = None;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Reads the rest of the body into memory
+    pub async fn read_all(&mut self) -> Result<Vec<u8>, ParseError> {
+        let mut bytes = Vec::with_capacity(self.length.unwrap_or_default() as usize);
+        while let Some(chunk) = self.chunk().await? {
+            bytes.extend_from_slice(&chunk);
+        }
+        Ok(bytes)
+    }
+
+    /// Trailer fields from a chunked body, available once it's been read
+    pub fn trailers(&self) -> Option<&Headers> {
+        self.trailers.as_ref()
+    }
+}
+
+impl Default for RequestBody {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl std::fmt::Debug for RequestBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RequestBody")
+            .field("length", &self.length)
+            .field("done", &self.rx.is_none())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn framing(fields: &[(&str, &str)], version: Version) -> Result<BodyFraming, ParseError> {
+        let mut headers = Headers::new();
+        for (name, value) in fields {
+            headers.append(*name, *value);
+        }
+        BodyFraming::from_headers(&headers, version, 100)
+    }
+
+    #[test]
+    fn content_length() {
+        assert!(matches!(
+            framing(&[], Version::Http11),
+            Ok(BodyFraming::None)
+        ));
+        assert!(matches!(
+            framing(&[("Content-Length", "0")], Version::Http11),
+            Ok(BodyFraming::None)
+        ));
+        assert!(matches!(
+            framing(&[("Content-Length", "10")], Version::Http11),
+            Ok(BodyFraming::Length(10))
+        ));
+        assert!(matches!(
+            framing(&[("Content-Length", "101")], Version::Http11),
+            Err(ParseError::ContentTooLarge)
+        ));
+        for value in ["", "-1", "+5", "0x10", "1 0", "99999999999999999999"] {
+            assert!(
+                matches!(
+                    framing(&[("Content-Length", value)], Version::Http11),
+                    Err(ParseError::BadRequest(_))
+                ),
+                "{value:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn duplicate_content_length_has_to_agree() {
+        assert!(matches!(
+            framing(
+                &[("Content-Length", "10"), ("Content-Length", "10")],
+                Version::Http11
+            ),
+            Ok(BodyFraming::Length(10))
+        ));
+        assert!(matches!(
+            framing(&[("Content-Length", "10, 10")], Version::Http11),
+            Ok(BodyFraming::Length(10))
+        ));
+        assert!(matches!(
+            framing(
+                &[("Content-Length", "10"), ("Content-Length", "11")],
+                Version::Http11
+            ),
+            Err(ParseError::BadRequest(_))
+        ));
+        assert!(matches!(
+            framing(&[("Content-Length", "10, 11")], Version::Http11),
+            Err(ParseError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn content_length_with_transfer_encoding_is_rejected() {
+        assert!(matches!(
+            framing(
+                &[("Content-Length", "10"), ("Transfer-Encoding", "chunked")],
+                Version::Http11
+            ),
+            Err(ParseError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn chunked_has_to_be_last() {
+        assert!(matches!(
+            framing(&[("Transfer-Encoding", "Chunked")], Version::Http11),
+            Ok(BodyFraming::Chunked)
+        ));
+        assert!(matches!(
+            framing(&[("Transfer-Encoding", "gzip, chunked")], Version::Http11),
+            Err(ParseError::UnsupportedTransferCoding)
+        ));
+        assert!(matches!(
+            framing(
+                &[
+                    ("Transfer-Encoding", "gzip"),
+                    ("Transfer-Encoding", "chunked")
+                ],
+                Version::Http11
+            ),
+            Err(ParseError::UnsupportedTransferCoding)
+        ));
+        for value in ["chunked, gzip", "gzip", "chunked, chunked", ""] {
+            assert!(
+                framing(&[("Transfer-Encoding", value)], Version::Http11).is_err(),
+                "{value:?}"
+            );
+        }
+        assert!(matches!(
+            framing(
+                &[
+                    ("Transfer-Encoding", "chunked"),
+                    ("Transfer-Encoding", "gzip")
+                ],
+                Version::Http11
+            ),
+            Err(ParseError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn no_transfer_encoding_in_http10() {
+        assert!(matches!(
+            framing(&[("Transfer-Encoding", "chunked")], Version::Http10),
+            Err(ParseError::BadRequest(_))
+        ));
+    }
+}
@@ -0,0 +1,372 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{Method, Request, Response, Status, date};
+
+/// Headers a 304 keeps from the response it replaces (RFC 9110 section 15.4.5)
+const NOT_MODIFIED_HEADERS: [&str; 6] = [
+    "Cache-Control",
+    "Content-Location",
+    "ETag",
+    "Expires",
+    "Last-Modified",
+    "Vary",
+];
+
+/// An entity-tag, e.g. `"abc"` or `W/"abc"` (RFC 9110 section 8.8.3)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct EntityTag<'a> {
+    weak: bool,
+
+    /// Between the quotes
+    opaque: &'a str,
+}
+
+impl<'a> EntityTag<'a> {
+    /// Parses one entity-tag, returning the rest of the input after it
+    fn parse(s: &'a str) -> Option<(Self, &'a str)> {
+        let (weak, s) = match s.strip_prefix("W/") {
+            Some(s) => (true, s),
+            None => (false, s),
+        };
+
+        let s = s.strip_prefix('"')?;
+        let end = s.find('"')?;
+        let opaque = &s[..end];
+        if !opaque
+            .bytes()
+            .all(|c| c == 0x21 || (0x23..0x7f).contains(&c) || c >= 0x80)
+        {
+            return None;
+        }
+        Some((Self { weak, opaque }, &s[end + 1..]))
+    }
+
+    /// Both have to be strong and the same (RFC 9110 section 8.8.3.2)
+    fn strong_eq(&self, other: &EntityTag) -> bool {
+        !self.weak && !other.weak && self.opaque == other.opaque
+    }
+
+    fn weak_eq(&self, other: &EntityTag) -> bool {
+        self.opaque == other.opaque
+    }
+}
+
+/// Whether an If-Match or If-None-Match value matches the current entity-tag
+///
+/// `*` matches any current representation. A list that can't be parsed
+/// matches nothing.
+fn matches(value: &str, etag: Option<&str>, eq: fn(&EntityTag, &EntityTag) -> bool) -> bool {
+    if value.trim() == "*" {
+        return true;
+    }
+
+    let Some((etag, _)) = etag.and_then(|etag| EntityTag::parse(etag.trim())) else {
+        return false;
+    };
+
+    let mut rest = value;
+    loop {
+        rest = rest.trim_start_matches([' ', '\t', ',']);
+        if rest.is_empty() {
+            return false;
+        }
+
+        let Some((tag, after)) = EntityTag::parse(rest) else {
+            return false;
+        };
+        if eq(&tag, &etag) {
+            return true;
+        }
+        rest = after;
+    }
+}
+
+/// Whether an If-Range entity-tag matches the current one (RFC 9110 section 13.1.5)
+///
+/// Only a strong match counts, since the range has to fit with the bytes
+/// the client already has.
+pub(crate) fn if_range_matches(value: &str, etag: Option<&str>) -> bool {
+    let Some((tag, rest)) = EntityTag::parse(value.trim()) else {
+        return false;
+    };
+    let Some((etag, _)) = etag.and_then(|etag| EntityTag::parse(etag.trim())) else {
+        return false;
+    };
+    rest.is_empty() && tag.strong_eq(&etag)
+}
+
+/// HTTP dates only go down to the second
+fn truncate(time: SystemTime) -> SystemTime {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => UNIX_EPOCH + Duration::from_secs(d.as_secs()),
+        Err(_) => time,
+    }
+}
+
+/// The conditional headers from a request (RFC 9110 section 13.1)
+///
+/// Taken from the request up front so they can be checked against the
+/// response once the handler has it.
+#[derive(Debug, Clone, Default)]
+pub struct Preconditions {
+    safe: bool,
+    if_match: Option<String>,
+    if_none_match: Option<String>,
+    if_modified_since: Option<SystemTime>,
+    if_unmodified_since: Option<SystemTime>,
+}
+
+impl Preconditions {
+    /// Dates that can't be parsed are ignored, as the RFC says to
+    pub fn from_request(request: &Request) -> Self {
+        let list = |name| {
+            let values: Vec<&str> = request.headers().get_all(name).collect();
+            (!values.is_empty()).then(|| values.join(", "))
+        };
+        let date = |name| request.header(name).and_then(date::parse_http_date);
+
+        Self {
+            safe: matches!(request.method(), Method::Get | Method::Head),
+            if_match: list("If-Match"),
+            if_none_match: list("If-None-Match"),
+            if_modified_since: date("If-Modified-Since"),
+            if_unmodified_since: date("If-Unmodified-Since"),
+        }
+    }
+
+    /// Checks the preconditions against the selected representation (RFC 9110 section 13.2.2)
+    ///
+    /// Returns 304 Not Modified or 412 Precondition Failed if the request
+    /// shouldn't go ahead. If-Match and If-None-Match take precedence over
+    /// the date they'd otherwise be checked alongside.
+    pub fn evaluate(
+        &self,
+        etag: Option<&str>,
+        last_modified: Option<SystemTime>,
+    ) -> Option<Status> {
+        let last_modified = last_modified.map(truncate);
+
+        if let Some(if_match) = &self.if_match {
+            if !matches(if_match, etag, |a, b| a.strong_eq(b)) {
+                return Some(Status::PreconditionFailed);
+            }
+        } else if let Some(since) = self.if_unmodified_since
+            && last_modified.is_some_and(|modified| modified > since)
+        {
+            return Some(Status::PreconditionFailed);
+        }
+
+        if let Some(if_none_match) = &self.if_none_match {
+            if matches(if_none_match, etag, |a, b| a.weak_eq(b)) {
+                return Some(if self.safe {
+                    Status::NotModified
+                } else {
+                    Status::PreconditionFailed
+                });
+            }
+        } else if let Some(since) = self.if_modified_since
+            && self.safe
+            && last_modified.is_some_and(|modified| modified <= since)
+        {
+            return Some(Status::NotModified);
+        }
+
+        None
+    }
+
+    /// Replaces a successful response with a 304 or 412 if a precondition says to
+    ///
+    /// Goes by the response's ETag and Last-Modified. Anything other than a
+    /// 2xx is left alone since the preconditions don't apply to it.
+    pub fn apply(&self, response: Response) -> Response {
+        if !response.status().is_success() {
+            return response;
+        }
+
+        let last_modified = response
+            .headers()
+            .get("Last-Modified")
+            .and_then(date::parse_http_date);
+        let Some(status) = self.evaluate(response.headers().get("ETag"), last_modified) else {
+            return response;
+        };
+
+        let mut replaced = Response::new(status);
+        if status == Status::NotModified {
+            for name in NOT_MODIFIED_HEADERS {
+                if let Some(value) = response.headers().get(name) {
+                    replaced.set_header(name, value);
+                }
+            }
+        }
+        replaced
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ETAG: Option<&str> = Some("\"xyzzy\"");
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn get() -> Preconditions {
+        Preconditions {
+            safe: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn if_match_is_strong() {
+        let check = |value: &str, etag| {
+            Preconditions {
+                if_match: Some(value.into()),
+                ..get()
+            }
+            .evaluate(etag, None)
+        };
+
+        assert_eq!(check("\"xyzzy\"", ETAG), None);
+        assert_eq!(check("\"r2d2xxxx\", \"xyzzy\"", ETAG), None);
+        assert_eq!(check("*", ETAG), None);
+        assert_eq!(check("\"other\"", ETAG), Some(Status::PreconditionFailed));
+        assert_eq!(check("W/\"xyzzy\"", ETAG), Some(Status::PreconditionFailed));
+        assert_eq!(
+            check("\"xyzzy\"", Some("W/\"xyzzy\"")),
+            Some(Status::PreconditionFailed)
+        );
+        assert_eq!(check("\"xyzzy\"", None), Some(Status::PreconditionFailed));
+    }
+
+    #[test]
+    fn if_none_match_is_weak() {
+        let check = |value: &str, etag| {
+            Preconditions {
+                if_none_match: Some(value.into()),
+                ..get()
+            }
+            .evaluate(etag, None)
+        };
+
+        assert_eq!(check("\"xyzzy\"", ETAG), Some(Status::NotModified));
+        assert_eq!(check("W/\"xyzzy\"", ETAG), Some(Status::NotModified));
+        assert_eq!(
+            check("\"xyzzy\"", Some("W/\"xyzzy\"")),
+            Some(Status::NotModified)
+        );
+        assert_eq!(check("*", ETAG), Some(Status::NotModified));
+        assert_eq!(check("\"other\", W/\"another\"", ETAG), None);
+        assert_eq!(check("\"xyzzy\"", None), None);
+    }
+
+    #[test]
+    fn if_none_match_fails_unsafe_methods() {
+        let preconditions = Preconditions {
+            safe: false,
+            if_none_match: Some("*".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            preconditions.evaluate(ETAG, None),
+            Some(Status::PreconditionFailed)
+        );
+    }
+
+    #[test]
+    fn dates() {
+        let unmodified = Preconditions {
+            if_unmodified_since: Some(at(1000)),
+            ..get()
+        };
+        assert_eq!(unmodified.evaluate(None, Some(at(1000))), None);
+        assert_eq!(
+            unmodified.evaluate(None, Some(at(1001))),
+            Some(Status::PreconditionFailed)
+        );
+        assert_eq!(unmodified.evaluate(None, None), None);
+
+        let modified = Preconditions {
+            if_modified_since: Some(at(1000)),
+            ..get()
+        };
+        assert_eq!(
+            modified.evaluate(None, Some(at(1000))),
+            Some(Status::NotModified)
+        );
+        // sub-second precision doesn't make it newer than the header's date
+        assert_eq!(
+            modified.evaluate(None, Some(at(1000) + Duration::from_millis(500))),
+            Some(Status::NotModified)
+        );
+        assert_eq!(modified.evaluate(None, Some(at(1001))), None);
+        assert_eq!(modified.evaluate(None, None), None);
+
+        // If-Modified-Since only applies to GET and HEAD
+        let unsafe_method = Preconditions {
+            safe: false,
+            ..modified
+        };
+        assert_eq!(unsafe_method.evaluate(None, Some(at(1000))), None);
+    }
+
+    // RFC 9110 section 13.2.2: an entity-tag check replaces the date one
+    #[test]
+    fn if_match_over_if_unmodified_since() {
+        let preconditions = Preconditions {
+            if_match: Some("\"xyzzy\"".into()),
+            if_unmodified_since: Some(at(1000)),
+            ..get()
+        };
+        assert_eq!(preconditions.evaluate(ETAG, Some(at(2000))), None);
+        assert_eq!(
+            preconditions.evaluate(Some("\"other\""), Some(at(500))),
+            Some(Status::PreconditionFailed)
+        );
+    }
+
+    #[test]
+    fn if_none_match_over_if_modified_since() {
+        let preconditions = Preconditions {
+            if_none_match: Some("\"other\"".into()),
+            if_modified_since: Some(at(1000)),
+            ..get()
+        };
+        assert_eq!(preconditions.evaluate(ETAG, Some(at(500))), None);
+
+        let preconditions = Preconditions {
+            if_none_match: Some("\"xyzzy\"".into()),
+            ..preconditions
+        };
+        assert_eq!(
+            preconditions.evaluate(ETAG, Some(at(2000))),
+            Some(Status::NotModified)
+        );
+    }
+
+    // a failed If-Match is reported before a matching If-None-Match
+    #[test]
+    fn if_match_first() {
+        let preconditions = Preconditions {
+            if_match: Some("\"other\"".into()),
+            if_none_match: Some("\"xyzzy\"".into()),
+            ..get()
+        };
+        assert_eq!(
+            preconditions.evaluate(ETAG, None),
+            Some(Status::PreconditionFailed)
+        );
+    }
+
+    #[test]
+    fn if_range_is_strong() {
+        assert!(if_range_matches("\"xyzzy\"", ETAG));
+        assert!(!if_range_matches("W/\"xyzzy\"", ETAG));
+        assert!(!if_range_matches("\"xyzzy\"", Some("W/\"xyzzy\"")));
+        assert!(!if_range_matches("\"xyzzy\", \"other\"", ETAG));
+        assert!(!if_range_matches("\"xyzzy\"", None));
+    }
+}
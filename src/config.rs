@@ -0,0 +1,822 @@
+use std::path::{Path, PathBuf};
+
+use tokio::time::Duration;
+use tracing::Level;
+
+use crate::{
+    BoxFuture, ConnectionConfig, DRAIN_TIMEOUT, HANDLER_TIMEOUT, Handler, Method, Request,
+    Response, Router, StaticFiles, Status, Symlinks, headers,
+    toml::{self, Entry, Table, Value},
+};
+
+/// Settings read from a config file
+///
+/// ```toml
+/// [timeouts]
+/// read = "500ms"
+/// idle = "5s"
+///
+/// [limits]
+/// max_body_size = "8MiB"
+///
+/// [logging]
+/// level = "info"
+///
+/// [[listener]]
+/// address = "0.0.0.0:8080"
+///
+/// [[listener]]
+/// address = "127.0.0.1:9090"
+/// admin = true
+/// idle_timeout = "30s"
+///
+/// [[route]]
+/// path = "/old"
+/// redirect = "/new"
+///
+/// [[static]]
+/// path = "/assets"
+/// root = "./public"
+/// ```
+///
+/// Durations are seconds or a string with a unit (ms, s, m or h), sizes
+/// are bytes or a string with a unit (KiB, MiB or GiB). The timeouts and
+/// limits are the defaults for listeners that don't set their own.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub listeners: Vec<ListenerConfig>,
+
+    /// Connection settings for listeners that don't override them
+    pub connection: ConnectionConfig,
+
+    /// How long a handler gets to produce a response
+    pub handler_timeout: Duration,
+
+    /// How long open connections get to finish when shutting down
+    pub drain_timeout: Duration,
+
+    /// What to raise the open files limit to at startup, if anything
+    pub open_files: Option<u64>,
+
+    pub routes: Vec<RouteConfig>,
+
+    /// Directories served as static files
+    pub statics: Vec<StaticConfig>,
+
+    pub logging: LoggingConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            listeners: Vec::new(),
+            connection: ConnectionConfig::default(),
+            handler_timeout: HANDLER_TIMEOUT,
+            drain_timeout: DRAIN_TIMEOUT,
+            open_files: None,
+            routes: Vec::new(),
+            statics: Vec::new(),
+            logging: LoggingConfig::default(),
+        }
+    }
+}
+
+/// A socket to listen on and the settings for its connections
+#[derive(Debug, Clone)]
+pub struct ListenerConfig {
+    pub address: String,
+
+    /// Serves the admin endpoints instead of the site
+    pub admin: bool,
+
+    pub connection: ConnectionConfig,
+}
+
+impl ListenerConfig {
+    pub fn new(address: impl Into<String>) -> Self {
+        Self {
+            address: address.into(),
+            admin: false,
+            connection: ConnectionConfig::default(),
+        }
+    }
+}
+
+/// A route answered straight from the config file
+#[derive(Debug, Clone)]
+pub struct RouteConfig {
+    pub method: Method,
+    pub path: String,
+    pub action: RouteAction,
+}
+
+#[derive(Debug, Clone)]
+pub enum RouteAction {
+    /// A fixed response
+    Respond {
+        status: Status,
+        content_type: Option<String>,
+        body: String,
+    },
+
+    Redirect {
+        status: Status,
+        location: String,
+    },
+}
+
+impl Handler for RouteAction {
+    fn call(&self, _request: Request) -> BoxFuture<'_, anyhow::Result<Response>> {
+        let response = match self {
+            RouteAction::Respond {
+                status,
+                content_type,
+                body,
+            } => {
+                let mut response = Response::new(*status);
+                if let Some(content_type) = content_type {
+                    response.set_header("Content-Type", content_type.as_str());
+                }
+                response.set_body(body.as_str());
+                response
+            }
+            RouteAction::Redirect { status, location } => {
+                let mut response = Response::new(*status);
+                response.set_header("Location", location.as_str());
+                response
+            }
+        };
+        Box::pin(async { Ok(response) })
+    }
+}
+
+/// A directory served under a path
+#[derive(Debug, Clone)]
+pub struct StaticConfig {
+    pub path: String,
+
+    /// Canonical, relative roots are resolved against the working directory
+    pub root: PathBuf,
+
+    pub symlinks: Symlinks,
+
+    /// The file that serves a directory, if any
+    pub index: Option<String>,
+
+    /// List directories that don't have an index file
+    pub autoindex: bool,
+
+    /// Send files with sendfile(2) where possible
+    pub sendfile: bool,
+}
+
+impl StaticConfig {
+    /// The route pattern that covers everything under the path
+    pub fn pattern(&self) -> String {
+        format!("{}/*file", self.path.trim_end_matches('/'))
+    }
+
+    pub fn files(&self) -> StaticFiles {
+        StaticFiles::new(&self.root)
+            .prefix(&self.path)
+            .symlinks(self.symlinks)
+            .index(self.index.clone())
+            .autoindex(self.autoindex)
+            .sendfile(self.sendfile)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LoggingConfig {
+    pub level: Level,
+
+    /// Colour the output with ANSI escapes
+    pub color: bool,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: Level::INFO,
+            color: true,
+        }
+    }
+}
+
+/// A problem with the config file, and the line it's on
+#[derive(Debug)]
+pub struct ConfigError {
+    line: usize,
+    message: String,
+}
+
+impl ConfigError {
+    pub(crate) fn new(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            message: message.into(),
+        }
+    }
+
+    /// The line the problem is on, 0 if it's not on any line in particular
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            0 => write!(f, "{}", self.message),
+            line => write!(f, "line {line}: {}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Reads and parses a config file
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let src = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("can't read {}: {e}", path.display()))?;
+        Self::parse(&src).map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))
+    }
+
+    pub fn parse(src: &str) -> Result<Self, ConfigError> {
+        let root = toml::parse(src)?;
+        let mut config = Config::default();
+
+        // the defaults have to be known before the listeners are read
+        if let Some(entry) = root.get("timeouts") {
+            parse_timeouts(table(entry)?, &mut config)?;
+        }
+        if let Some(entry) = root.get("limits") {
+            parse_limits(table(entry)?, &mut config)?;
+        }
+
+        // catches duplicates and bad patterns the router would panic on
+        let mut router = Router::new();
+
+        for entry in &root.entries {
+            match entry.key.as_str() {
+                "timeouts" | "limits" => (),
+                "logging" => config.logging = parse_logging(table(entry)?)?,
+                "listener" => {
+                    for table in table_array(entry)? {
+                        let listener = parse_listener(table, config.connection)?;
+                        if config
+                            .listeners
+                            .iter()
+                            .any(|l| l.address == listener.address)
+                        {
+                            return Err(ConfigError::new(
+                                table.line,
+                                format!("{} is already a listener", listener.address),
+                            ));
+                        }
+                        config.listeners.push(listener);
+                    }
+                }
+                "route" => {
+                    for table in table_array(entry)? {
+                        let route = parse_route(table)?;
+                        router = router
+                            .try_route(route.method.clone(), &route.path, route.action.clone())
+                            .map_err(|e| ConfigError::new(table.line, e))?;
+                        config.routes.push(route);
+                    }
+                }
+                "static" => {
+                    for table in table_array(entry)? {
+                        let files = parse_static(table)?;
+                        router = router
+                            .try_route(Method::Get, &files.pattern(), files.files())
+                            .map_err(|e| ConfigError::new(table.line, e))?;
+                        config.statics.push(files);
+                    }
+                }
+                key => return Err(unknown_key(entry, key)),
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// A router serving the configured routes and static files
+    pub fn router(&self) -> Router {
+        let router = self.routes.iter().fold(Router::new(), |router, route| {
+            router.route(route.method.clone(), &route.path, route.action.clone())
+        });
+        self.statics.iter().fold(router, |router, files| {
+            router.get(&files.pattern(), files.files())
+        })
+    }
+}
+
+fn parse_timeouts(table: &Table, config: &mut Config) -> Result<(), ConfigError> {
+    for entry in &table.entries {
+        match entry.key.as_str() {
+            "read" => config.connection.read_timeout = duration(entry)?,
+            "write" => config.connection.write_timeout = duration(entry)?,
+            "idle" => config.connection.idle_timeout = duration(entry)?,
+            "handler" => config.handler_timeout = duration(entry)?,
+            "drain" => config.drain_timeout = duration(entry)?,
+            key => return Err(unknown_key(entry, key)),
+        }
+    }
+    Ok(())
+}
+
+fn parse_limits(table: &Table, config: &mut Config) -> Result<(), ConfigError> {
+    for entry in &table.entries {
+        // the one limit that's for the whole process rather than per listener
+        if entry.key == "open_files" {
+            config.open_files = Some(integer(entry, 1)?);
+        } else if !parse_limit(entry, &mut config.connection)? {
+            return Err(unknown_key(entry, &entry.key));
+        }
+    }
+    Ok(())
+}
+
+/// Sets a limit from a [limits] or [[listener]] entry, false if it isn't one
+fn parse_limit(entry: &Entry, connection: &mut ConnectionConfig) -> Result<bool, ConfigError> {
+    match entry.key.as_str() {
+        "max_header_size" => connection.max_header_size = size(entry, 1)?,
+        "max_uri_length" => connection.max_uri_length = size(entry, 1)?,
+        "max_body_size" => connection.max_body_size = size(entry, 0)?,
+        "max_requests" => connection.max_requests = integer(entry, 1)?,
+        "pipeline_depth" => connection.pipeline_depth = integer(entry, 1)?,
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+fn parse_listener(
+    table: &Table,
+    connection: ConnectionConfig,
+) -> Result<ListenerConfig, ConfigError> {
+    let Some(address) = table.get("address") else {
+        return Err(ConfigError::new(
+            table.line,
+            "listener is missing an address",
+        ));
+    };
+
+    let mut listener = ListenerConfig {
+        address: string(address)?,
+        admin: false,
+        connection,
+    };
+
+    let port = listener.address.rsplit_once(':').map(|(_, port)| port);
+    if port.and_then(|port| port.parse::<u16>().ok()).is_none() {
+        return Err(ConfigError::new(
+            address.line,
+            format!("address {} needs a port", listener.address),
+        ));
+    }
+
+    for entry in &table.entries {
+        let connection = &mut listener.connection;
+        match entry.key.as_str() {
+            "address" => (),
+            "admin" => listener.admin = boolean(entry)?,
+            "read_timeout" => connection.read_timeout = duration(entry)?,
+            "write_timeout" => connection.write_timeout = duration(entry)?,
+            "idle_timeout" => connection.idle_timeout = duration(entry)?,
+            key => {
+                if !parse_limit(entry, connection)? {
+                    return Err(unknown_key(entry, key));
+                }
+            }
+        }
+    }
+
+    Ok(listener)
+}
+
+fn parse_route(table: &Table) -> Result<RouteConfig, ConfigError> {
+    let Some(path) = table.get("path") else {
+        return Err(ConfigError::new(table.line, "route is missing a path"));
+    };
+    let path = string(path)?;
+
+    let mut method = Method::Get;
+    let mut status = None;
+    let mut content_type = None;
+    let mut body = None;
+    let mut redirect = None;
+    for entry in &table.entries {
+        match entry.key.as_str() {
+            "path" => (),
+            "method" => {
+                method = Method::try_from(string(entry)?.as_str())
+                    .map_err(|_| ConfigError::new(entry.line, "invalid method"))?;
+            }
+            "status" => status = Some(integer::<u16>(entry, 100)?),
+            "content_type" => content_type = Some(header_value(entry)?),
+            "body" => body = Some(string(entry)?),
+            "redirect" => redirect = Some(header_value(entry)?),
+            key => return Err(unknown_key(entry, key)),
+        }
+    }
+
+    let status = |default: Status, valid: std::ops::Range<u16>, expected: &str| {
+        let code = status.unwrap_or(default.code());
+        match Status::from_code(code) {
+            Some(status) if valid.contains(&code) => Ok(status),
+            _ => Err(ConfigError::new(
+                table.get("status").map_or(table.line, |entry| entry.line),
+                format!("status should be {expected}"),
+            )),
+        }
+    };
+
+    let action = match redirect {
+        Some(_) if body.is_some() || content_type.is_some() => {
+            return Err(ConfigError::new(
+                table.line,
+                "a redirect route can't have a body",
+            ));
+        }
+        Some(location) => RouteAction::Redirect {
+            status: status(Status::Found, 300..400, "3xx")?,
+            location,
+        },
+        None => RouteAction::Respond {
+            status: status(Status::Ok, 200..600, "2xx to 5xx")?,
+            content_type,
+            body: body.unwrap_or_default(),
+        },
+    };
+
+    Ok(RouteConfig {
+        method,
+        path,
+        action,
+    })
+}
+
+fn parse_static(table: &Table) -> Result<StaticConfig, ConfigError> {
+    let Some(root) = table.get("root") else {
+        return Err(ConfigError::new(table.line, "static is missing a root"));
+    };
+
+    // checked now so a missing root is caught by --check-config, not by requests
+    let given = string(root)?;
+    let root = match std::fs::canonicalize(&given) {
+        Ok(path) if path.is_dir() => path,
+        Ok(_) => {
+            return Err(ConfigError::new(
+                root.line,
+                format!("root {given} isn't a directory"),
+            ));
+        }
+        Err(e) => return Err(ConfigError::new(root.line, format!("root {given}: {e}"))),
+    };
+
+    let mut files = StaticConfig {
+        path: "/".to_string(),
+        root,
+        symlinks: Symlinks::default(),
+        index: Some("index.html".to_string()),
+        autoindex: false,
+        sendfile: true,
+    };
+
+    for entry in &table.entries {
+        match entry.key.as_str() {
+            "root" => (),
+            "path" => {
+                files.path = string(entry)?;
+
+                // the path is matched literally, so no patterns
+                if !files.path.starts_with('/') || files.path.contains(['*', ':']) {
+                    return Err(ConfigError::new(
+                        entry.line,
+                        "path should be a plain path starting with /",
+                    ));
+                }
+            }
+            "symlinks" => {
+                files.symlinks = string(entry)?.parse().map_err(|_| {
+                    ConfigError::new(entry.line, "symlinks should be follow, within_root or deny")
+                })?;
+            }
+            "index" => {
+                files.index = match &entry.value {
+                    Value::String(index) if !index.is_empty() && !index.contains('/') => {
+                        Some(index.clone())
+                    }
+                    Value::Boolean(false) => None,
+                    _ => {
+                        return Err(ConfigError::new(
+                            entry.line,
+                            "index should be a file name or false",
+                        ));
+                    }
+                };
+            }
+            "autoindex" => files.autoindex = boolean(entry)?,
+            "sendfile" => files.sendfile = boolean(entry)?,
+            key => return Err(unknown_key(entry, key)),
+        }
+    }
+
+    Ok(files)
+}
+
+fn parse_logging(table: &Table) -> Result<LoggingConfig, ConfigError> {
+    let mut logging = LoggingConfig::default();
+    for entry in &table.entries {
+        match entry.key.as_str() {
+            "level" => {
+                logging.level = string(entry)?.parse().map_err(|_| {
+                    ConfigError::new(
+                        entry.line,
+                        "level should be error, warn, info, debug or trace",
+                    )
+                })?;
+            }
+            "color" => logging.color = boolean(entry)?,
+            key => return Err(unknown_key(entry, key)),
+        }
+    }
+    Ok(logging)
+}
+
+fn unknown_key(entry: &Entry, key: &str) -> ConfigError {
+    ConfigError::new(entry.line, format!("unknown setting {key}"))
+}
+
+fn type_error(entry: &Entry, expected: &str) -> ConfigError {
+    ConfigError::new(
+        entry.line,
+        format!(
+            "{} should be {expected}, not {}",
+            entry.key,
+            entry.value.type_name()
+        ),
+    )
+}
+
+fn table(entry: &Entry) -> Result<&Table, ConfigError> {
+    match &entry.value {
+        Value::Table(table) => Ok(table),
+        _ => Err(ConfigError::new(
+            entry.line,
+            format!("{} should be written as [{}]", entry.key, entry.key),
+        )),
+    }
+}
+
+fn table_array(entry: &Entry) -> Result<&[Table], ConfigError> {
+    match &entry.value {
+        Value::TableArray(tables) => Ok(tables),
+        _ => Err(ConfigError::new(
+            entry.line,
+            format!("{} should be written as [[{}]]", entry.key, entry.key),
+        )),
+    }
+}
+
+fn string(entry: &Entry) -> Result<String, ConfigError> {
+    match &entry.value {
+        Value::String(s) => Ok(s.clone()),
+        _ => Err(type_error(entry, "a string")),
+    }
+}
+
+/// A string that's sent as a header field value
+///
+/// Control characters are refused here rather than at request time, a CR
+/// or LF would otherwise let the value add header fields of its own.
+fn header_value(entry: &Entry) -> Result<String, ConfigError> {
+    let value = string(entry)?;
+    if !headers::is_field_value(value.as_bytes()) {
+        return Err(ConfigError::new(
+            entry.line,
+            format!("{} can't contain control characters", entry.key),
+        ));
+    }
+    Ok(value)
+}
+
+fn boolean(entry: &Entry) -> Result<bool, ConfigError> {
+    match entry.value {
+        Value::Boolean(b) => Ok(b),
+        _ => Err(type_error(entry, "true or false")),
+    }
+}
+
+/// An integer no smaller than min, converted to whatever the setting needs
+fn integer<T: TryFrom<i64>>(entry: &Entry, min: i64) -> Result<T, ConfigError> {
+    match entry.value {
+        Value::Integer(n) => at_least(entry, n, min),
+        _ => Err(type_error(entry, "an integer")),
+    }
+}
+
+fn at_least<T: TryFrom<i64>>(entry: &Entry, n: i64, min: i64) -> Result<T, ConfigError> {
+    if n < min {
+        return Err(ConfigError::new(
+            entry.line,
+            format!("{} should be at least {min}", entry.key),
+        ));
+    }
+    T::try_from(n).map_err(|_| ConfigError::new(entry.line, format!("{} is too large", entry.key)))
+}
+
+/// Splits "500ms" into 500 and "ms"
+fn split_unit(s: &str) -> Option<(i64, &str)> {
+    let s = s.trim();
+    let idx = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let n = s[..idx].parse().ok()?;
+    Some((n, s[idx..].trim()))
+}
+
+/// A duration of at least a millisecond
+fn duration(entry: &Entry) -> Result<Duration, ConfigError> {
+    let millis = match &entry.value {
+        Value::Integer(secs) => secs.checked_mul(1000),
+        Value::String(s) => {
+            let invalid = || {
+                ConfigError::new(
+                    entry.line,
+                    format!("{} should be a number followed by ms, s, m or h", entry.key),
+                )
+            };
+            let (n, unit) = split_unit(s).ok_or_else(invalid)?;
+            match unit {
+                "ms" => Some(n),
+                "s" => n.checked_mul(1000),
+                "m" => n.checked_mul(60 * 1000),
+                "h" => n.checked_mul(60 * 60 * 1000),
+                _ => return Err(invalid()),
+            }
+        }
+        _ => return Err(type_error(entry, "a duration")),
+    };
+
+    let millis = millis
+        .ok_or_else(|| ConfigError::new(entry.line, format!("{} is too large", entry.key)))?;
+    at_least(entry, millis, 1).map(Duration::from_millis)
+}
+
+/// A size in bytes no smaller than min
+fn size<T: TryFrom<i64>>(entry: &Entry, min: i64) -> Result<T, ConfigError> {
+    let bytes = match &entry.value {
+        Value::Integer(n) => Some(*n),
+        Value::String(s) => {
+            let invalid = || {
+                ConfigError::new(
+                    entry.line,
+                    format!(
+                        "{} should be a number followed by KiB, MiB or GiB",
+                        entry.key
+                    ),
+                )
+            };
+            let (n, unit) = split_unit(s).ok_or_else(invalid)?;
+            match unit {
+                "" | "B" => Some(n),
+                "KiB" => n.checked_mul(1 << 10),
+                "MiB" => n.checked_mul(1 << 20),
+                "GiB" => n.checked_mul(1 << 30),
+                _ => return Err(invalid()),
+            }
+        }
+        _ => return Err(type_error(entry, "a size")),
+    };
+
+    let bytes =
+        bytes.ok_or_else(|| ConfigError::new(entry.line, format!("{} is too large", entry.key)))?;
+    at_least(entry, bytes, min)
+}
+
+/// Writes the config back out in the file format, with every default filled in
+impl std::fmt::Display for Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let connection = &self.connection;
+        writeln!(f, "[timeouts]")?;
+        writeln!(f, "read = {}", format_duration(connection.read_timeout))?;
+        writeln!(f, "write = {}", format_duration(connection.write_timeout))?;
+        writeln!(f, "idle = {}", format_duration(connection.idle_timeout))?;
+        writeln!(f, "handler = {}", format_duration(self.handler_timeout))?;
+        writeln!(f, "drain = {}", format_duration(self.drain_timeout))?;
+
+        writeln!(f, "\n[limits]")?;
+        if let Some(open_files) = self.open_files {
+            writeln!(f, "open_files = {open_files}")?;
+        }
+        write_limits(f, connection)?;
+
+        writeln!(f, "\n[logging]")?;
+        writeln!(
+            f,
+            "level = {}",
+            quote(&self.logging.level.to_string().to_lowercase())
+        )?;
+        writeln!(f, "color = {}", self.logging.color)?;
+
+        for listener in &self.listeners {
+            let connection = &listener.connection;
+            writeln!(f, "\n[[listener]]")?;
+            writeln!(f, "address = {}", quote(&listener.address))?;
+            writeln!(f, "admin = {}", listener.admin)?;
+            writeln!(
+                f,
+                "read_timeout = {}",
+                format_duration(connection.read_timeout)
+            )?;
+            writeln!(
+                f,
+                "write_timeout = {}",
+                format_duration(connection.write_timeout)
+            )?;
+            writeln!(
+                f,
+                "idle_timeout = {}",
+                format_duration(connection.idle_timeout)
+            )?;
+            write_limits(f, connection)?;
+        }
+
+        for route in &self.routes {
+            writeln!(f, "\n[[route]]")?;
+            writeln!(f, "method = {}", quote(&route.method.to_string()))?;
+            writeln!(f, "path = {}", quote(&route.path))?;
+            match &route.action {
+                RouteAction::Respond {
+                    status,
+                    content_type,
+                    body,
+                } => {
+                    writeln!(f, "status = {}", status.code())?;
+                    if let Some(content_type) = content_type {
+                        writeln!(f, "content_type = {}", quote(content_type))?;
+                    }
+                    writeln!(f, "body = {}", quote(body))?;
+                }
+                RouteAction::Redirect { status, location } => {
+                    writeln!(f, "status = {}", status.code())?;
+                    writeln!(f, "redirect = {}", quote(location))?;
+                }
+            }
+        }
+
+        for files in &self.statics {
+            writeln!(f, "\n[[static]]")?;
+            writeln!(f, "path = {}", quote(&files.path))?;
+            writeln!(f, "root = {}", quote(&files.root.to_string_lossy()))?;
+            writeln!(f, "symlinks = {}", quote(&files.symlinks.to_string()))?;
+            match &files.index {
+                Some(index) => writeln!(f, "index = {}", quote(index))?,
+                None => writeln!(f, "index = false")?,
+            }
+            writeln!(f, "autoindex = {}", files.autoindex)?;
+            writeln!(f, "sendfile = {}", files.sendfile)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn write_limits(
+    f: &mut std::fmt::Formatter<'_>,
+    connection: &ConnectionConfig,
+) -> std::fmt::Result {
+    writeln!(f, "max_header_size = {}", connection.max_header_size)?;
+    writeln!(f, "max_uri_length = {}", connection.max_uri_length)?;
+    writeln!(f, "max_body_size = {}", connection.max_body_size)?;
+    writeln!(f, "max_requests = {}", connection.max_requests)?;
+    writeln!(f, "pipeline_depth = {}", connection.pipeline_depth)
+}
+
+fn format_duration(duration: Duration) -> String {
+    match duration.as_millis() {
+        millis if millis % 1000 == 0 => format!("\"{}s\"", millis / 1000),
+        millis => format!("\"{millis}ms\""),
+    }
+}
+
+/// Quotes a string as a TOML basic string
+fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,961 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{
+        TcpStream,
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+    },
+    sync::{
+        mpsc,
+        oneshot::{self, error::TryRecvError},
+        watch,
+    },
+    task::JoinHandle,
+    time::{Duration, Instant, timeout, timeout_at},
+};
+
+use tracing::{debug, error};
+
+use crate::{
+    Body, BodyFraming, Frame, Handler, Headers, IDLE_TIMEOUT, MAX_BODY_SIZE, MAX_HEADER_SIZE,
+    MAX_REQUESTS_PER_CONNECTION, MAX_URI_LENGTH, Method, PIPELINE_DEPTH, ParseError, READ_TIMEOUT,
+    Request, RequestBody, Response, Status, Version, WRITE_TIMEOUT, headers, parse_request,
+    swap::Swap,
+};
+
+const READ_CHUNK_SIZE: usize = 1024 * 4;
+
+/// Largest piece of body handed to the handler at once
+const BODY_CHUNK_SIZE: usize = 1024 * 64;
+
+/// Longest chunk-size line (with extensions) we'll accept
+const MAX_CHUNK_LINE: usize = 1024;
+
+type BodyTx = mpsc::Sender<Result<Frame, ParseError>>;
+
+/// Per-connection settings
+#[derive(Debug, Copy, Clone)]
+pub struct ConnectionConfig {
+    /// How long to wait for the next request on a persistent connection
+    pub idle_timeout: Duration,
+
+    /// How many requests to serve before closing the connection
+    pub max_requests: usize,
+
+    /// How many pipelined requests can be waiting on a response
+    pub pipeline_depth: usize,
+
+    /// How long a client gets to send the rest of a request once it's started
+    pub read_timeout: Duration,
+
+    /// How long a client gets to accept each write of a response
+    pub write_timeout: Duration,
+
+    /// Largest request head (request-line and headers) accepted, in bytes
+    pub max_header_size: usize,
+
+    /// Longest request-target accepted, in bytes
+    pub max_uri_length: usize,
+
+    /// Largest request body accepted, in bytes
+    pub max_body_size: u64,
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout: IDLE_TIMEOUT,
+            max_requests: MAX_REQUESTS_PER_CONNECTION,
+            pipeline_depth: PIPELINE_DEPTH,
+            read_timeout: READ_TIMEOUT,
+            write_timeout: WRITE_TIMEOUT,
+            max_header_size: MAX_HEADER_SIZE,
+            max_uri_length: MAX_URI_LENGTH,
+            max_body_size: MAX_BODY_SIZE,
+        }
+    }
+}
+
+/// A client connection and its read buffer
+///
+/// Bytes past the end of a request head stay in the buffer
+/// so they can be used for the body or the next pipelined request.
+pub struct Connection {
+    stream: OwnedReadHalf,
+    config: ConnectionConfig,
+    buf: Vec<u8>,
+
+    // how much of buf has already been searched for the head terminator
+    scanned: usize,
+}
+
+impl Connection {
+    pub fn new(stream: OwnedReadHalf, config: ConnectionConfig) -> Self {
+        Self {
+            stream,
+            config,
+            buf: Vec::with_capacity(READ_CHUNK_SIZE),
+            scanned: 0,
+        }
+    }
+
+    /// Reads the next request head, or None if the client closed the
+    /// connection (or went idle) before sending anything
+    pub async fn read_request(&mut self) -> Result<Option<Request>, ParseError> {
+        // the idle timeout covers waiting for a request to start,
+        // the read timeout covers receiving the rest of it
+        let mut started = !self.buf.is_empty();
+        let mut deadline = if started {
+            Instant::now() + self.config.read_timeout
+        } else {
+            Instant::now() + self.config.idle_timeout
+        };
+
+        let end = loop {
+            // ignore empty lines ahead of the request-line (RFC 9112 section 2.2)
+            let leading = self
+                .buf
+                .iter()
+                .take_while(|&&c| c == b'\r' || c == b'\n')
+                .count();
+            if leading > 0 {
+                self.buf.drain(..leading);
+                self.scanned = 0;
+            }
+
+            if let Some(end) = self.find_head_end() {
+                break end;
+            }
+
+            if self.buf.len() >= self.config.max_header_size {
+                return Err(self.oversize_error());
+            }
+
+            self.buf.reserve(READ_CHUNK_SIZE);
+            let n = match timeout_at(deadline, self.stream.read_buf(&mut self.buf)).await {
+                Ok(Ok(n)) => n,
+                Ok(Err(e)) => return Err(e.into()),
+                Err(_) if self.buf.is_empty() => return Ok(None),
+                Err(_) => return Err(ParseError::Timeout),
+            };
+
+            if n == 0 {
+                if self.buf.is_empty() {
+                    return Ok(None);
+                }
+                return Err(ParseError::Closed);
+            }
+
+            if !started {
+                started = true;
+                deadline = Instant::now() + self.config.read_timeout;
+            }
+        };
+
+        if end > self.config.max_header_size {
+            return Err(self.oversize_error());
+        }
+
+        let head: Vec<u8> = self.buf.drain(..end).collect();
+        self.scanned = 0;
+
+        parse_request(&head, self.config.max_uri_length).map(Some)
+    }
+
+    /// Feeds the request body to the handler through the channel
+    ///
+    /// The body is read to the end even if the handler stops listening,
+    /// otherwise the next request on the connection couldn't be found.
+    pub async fn read_body(&mut self, framing: BodyFraming, tx: BodyTx) -> Result<(), ParseError> {
+        match framing {
+            BodyFraming::None => Ok(()),
+            BodyFraming::Length(length) => self.read_sized_body(length, &tx).await,
+            BodyFraming::Chunked => self.read_chunked_body(&tx).await,
+        }
+    }
+
+    async fn read_sized_body(&mut self, length: u64, tx: &BodyTx) -> Result<(), ParseError> {
+        let mut remaining = length;
+        while remaining > 0 {
+            let data = self
+                .take(remaining.min(BODY_CHUNK_SIZE as u64) as usize)
+                .await?;
+            remaining -= data.len() as u64;
+
+            // a closed channel just means the handler doesn't want the rest
+            let _ = tx.send(Ok(Frame::Data(data))).await;
+        }
+        Ok(())
+    }
+
+    /// Decodes the chunked transfer coding (RFC 9112 section 7.1)
+    async fn read_chunked_body(&mut self, tx: &BodyTx) -> Result<(), ParseError> {
+        let mut total: u64 = 0;
+        loop {
+            // chunk-size [ chunk-ext ] CRLF, extensions are ignored
+            let line = self.read_line(MAX_CHUNK_LINE).await?;
+            let size = line.split(|&c| c == b';').next().unwrap_or_default();
+            let size = headers::trim_ows(size);
+            if size.is_empty() || size.len() > 16 || !size.iter().all(u8::is_ascii_hexdigit) {
+                return Err(ParseError::BadRequest("invalid chunk size"));
+            }
+
+            // all hex digits so this can't fail
+            let size = u64::from_str_radix(str::from_utf8(size).unwrap_or_default(), 16)
+                .map_err(|_| ParseError::BadRequest("invalid chunk size"))?;
+            if size == 0 {
+                break;
+            }
+
+            total = total.saturating_add(size);
+            if total > self.config.max_body_size {
+                return Err(ParseError::ContentTooLarge);
+            }
+
+            self.read_sized_body(size, tx).await?;
+
+            if !self.read_line(0).await?.is_empty() {
+                return Err(ParseError::BadRequest("missing chunk terminator"));
+            }
+        }
+
+        let mut trailers = Headers::new();
+        let mut trailers_size = 0;
+        loop {
+            let line = self.read_line(self.config.max_header_size).await?;
+            if line.is_empty() {
+                break;
+            }
+
+            trailers_size += line.len() + 2;
+            if trailers_size > self.config.max_header_size {
+                return Err(ParseError::HeadersTooLarge);
+            }
+
+            let (name, value) = headers::parse_field(&line)?;
+            trailers.append(name, value);
+        }
+
+        if !trailers.is_empty() {
+            let _ = tx.send(Ok(Frame::Trailers(trailers))).await;
+        }
+        Ok(())
+    }
+
+    /// Reads more from the client into the buffer
+    async fn fill(&mut self) -> Result<(), ParseError> {
+        self.buf.reserve(READ_CHUNK_SIZE);
+        match timeout(
+            self.config.read_timeout,
+            self.stream.read_buf(&mut self.buf),
+        )
+        .await
+        {
+            Ok(Ok(0)) => Err(ParseError::Closed),
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(e)) => Err(e.into()),
+            Err(_) => Err(ParseError::Timeout),
+        }
+    }
+
+    /// Takes up to max bytes, reading more if nothing is buffered
+    async fn take(&mut self, max: usize) -> Result<Vec<u8>, ParseError> {
+        if self.buf.is_empty() {
+            self.fill().await?;
+        }
+
+        let n = max.min(self.buf.len());
+        Ok(self.buf.drain(..n).collect())
+    }
+
+    /// Takes a line without its CRLF, failing if it runs past max bytes
+    async fn read_line(&mut self, max: usize) -> Result<Vec<u8>, ParseError> {
+        let mut scanned = 0;
+        loop {
+            if let Some(idx) = self.buf[scanned..].windows(2).position(|w| w == b"\r\n") {
+                let end = scanned + idx;
+                let line = self.buf[..end].to_vec();
+                self.buf.drain(..end + 2);
+                return Ok(line);
+            }
+
+            if self.buf.len() > max + 1 {
+                return Err(ParseError::BadRequest("line too long"));
+            }
+
+            // back up in case the CRLF was split across reads
+            scanned = self.buf.len().saturating_sub(1);
+            self.fill().await?;
+        }
+    }
+
+    /// A head that doesn't even fit the request-line is a URI problem
+    fn oversize_error(&self) -> ParseError {
+        let line_end = self.buf.windows(2).position(|w| w == b"\r\n");
+        match line_end {
+            Some(idx) if idx <= self.config.max_header_size => ParseError::HeadersTooLarge,
+            _ => ParseError::UriTooLong,
+        }
+    }
+
+    /// Returns the length of the buffered head including the CRLFCRLF terminator
+    fn find_head_end(&mut self) -> Option<usize> {
+        // back up in case the terminator was split across reads
+        let start = self.scanned.saturating_sub(3);
+        let found = self.buf[start..]
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .map(|idx| start + idx + 4);
+
+        self.scanned = self.buf.len();
+        found
+    }
+}
+
+/// What a listener serves requests with
+///
+/// Replaced as a whole when the config is reloaded, each request
+/// keeps using the one that was current when it arrived.
+pub(crate) struct Service {
+    pub handler: Arc<dyn Handler>,
+    pub config: ConnectionConfig,
+}
+
+/// A spawned handler, aborted if it's dropped before it finishes
+///
+/// Handlers run on their own tasks so pipelined requests are handled
+/// concurrently, but they belong to the connection: once it's gone, e.g.
+/// cut off when the drain timeout runs out, nobody will see their responses.
+struct HandlerTask(JoinHandle<anyhow::Result<Response>>);
+
+impl HandlerTask {
+    fn spawn(future: impl Future<Output = anyhow::Result<Response>> + Send + 'static) -> Self {
+        Self(tokio::spawn(future))
+    }
+}
+
+impl Future for HandlerTask {
+    type Output = <JoinHandle<anyhow::Result<Response>> as Future>::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0).poll(cx)
+    }
+}
+
+impl Drop for HandlerTask {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+struct PendingResponse {
+    response: HandlerTask,
+    write_timeout: Duration,
+    version: Version,
+    keep_alive: bool,
+    head_only: bool,
+    expect_continue: Option<ExpectContinue>,
+
+    /// Fires once the request body has been read, failing with the status
+    /// to answer with instead if it couldn't be
+    body_read: Option<oneshot::Receiver<Result<(), Option<Status>>>>,
+}
+
+/// Coordinates a 100-continue between the handler, the writer and the reader
+struct ExpectContinue {
+    /// Fires when the handler starts reading the body
+    body_read: oneshot::Receiver<()>,
+
+    /// Tells the reader whether the client was asked to send the body
+    send_body: oneshot::Sender<bool>,
+}
+
+/// Serves requests on the connection until either side closes it
+///
+/// Requests are read and handled as they arrive, up to the pipeline depth,
+/// while responses are written back in the order the requests came in.
+/// Only safe requests are handled at the same time as others.
+///
+/// Once the server starts shutting down no more requests are read, and
+/// responses are sent with Connection: close.
+pub(crate) async fn handle_connection(
+    stream: TcpStream,
+    service: Arc<Swap<Service>>,
+    shutdown: watch::Receiver<bool>,
+) -> anyhow::Result<()> {
+    let (read_half, write_half) = stream.into_split();
+    let config = service.load().config;
+    let (tx, rx) = mpsc::channel(config.pipeline_depth.max(1));
+
+    let reader = read_requests(
+        Connection::new(read_half, config),
+        tx,
+        service,
+        shutdown.clone(),
+    );
+    let writer = write_responses(write_half, rx, shutdown);
+    tokio::pin!(reader, writer);
+
+    tokio::select! {
+        result = &mut reader => {
+            // anything already read still gets its response
+            let written = writer.await;
+            result.and(written)
+        }
+        // the writer only stops early when the connection is closing
+        result = &mut writer => result,
+    }
+}
+
+async fn read_requests(
+    mut conn: Connection,
+    tx: mpsc::Sender<PendingResponse>,
+    service: Arc<Swap<Service>>,
+    mut shutdown: watch::Receiver<bool>,
+) -> anyhow::Result<()> {
+    let mut served = 0;
+
+    // handlers that may still be running, with whether their request was
+    // safe and a receiver that's closed once they finish
+    let mut running: Vec<(bool, oneshot::Receiver<()>)> = Vec::new();
+
+    loop {
+        // a reloaded config applies from the next request on
+        let service = service.load();
+        let config = service.config;
+        conn.config = config;
+
+        // a request that's partly read when the server shuts down is dropped,
+        // the client can't have had a response to it yet so it's safe to retry
+        let read = tokio::select! {
+            read = conn.read_request() => read,
+            _ = shutdown.wait_for(|&shutdown| shutdown) => return Ok(()),
+        };
+
+        let mut request = match read {
+            Ok(Some(request)) => request,
+            Ok(None) => return Ok(()),
+            Err(ParseError::Io(e)) => return Err(e.into()),
+            Err(e) => {
+                reject(&tx, e, config.write_timeout).await;
+                return Ok(());
+            }
+        };
+        served += 1;
+
+        // size limits are checked here so an oversized upload
+        // gets its 413 without being sent 100 Continue first
+        let (framing, expects_continue) = match BodyFraming::from_headers(
+            request.headers(),
+            request.version(),
+            config.max_body_size,
+        )
+        .and_then(|framing| Ok((framing, request.expects_continue()?)))
+        {
+            Ok(framing) => framing,
+            Err(e) => {
+                reject(&tx, e, config.write_timeout).await;
+                return Ok(());
+            }
+        };
+
+        let body_tx = match framing {
+            BodyFraming::None => None,
+            BodyFraming::Length(length) => {
+                let (body_tx, body) = RequestBody::channel(Some(length));
+                request.set_body(body);
+                Some(body_tx)
+            }
+            BodyFraming::Chunked => {
+                let (body_tx, body) = RequestBody::channel(None);
+                request.set_body(body);
+                Some(body_tx)
+            }
+        };
+
+        let mut body_read = None;
+        let body_done = body_tx.as_ref().map(|_| {
+            let (done_tx, done_rx) = oneshot::channel();
+            body_read = Some(done_rx);
+            done_tx
+        });
+
+        let mut send_body = None;
+        let expect_continue = if expects_continue && body_tx.is_some() {
+            let (send_body_tx, send_body_rx) = oneshot::channel();
+            send_body = Some(send_body_rx);
+            Some(ExpectContinue {
+                body_read: request.body_mut().on_first_read(),
+                send_body: send_body_tx,
+            })
+        } else {
+            None
+        };
+
+        let version = request.version();
+        let keep_alive = request.keep_alive() && served < config.max_requests;
+        let head_only = *request.method() == Method::Head;
+
+        // pipelined requests are only handled at the same time if they're
+        // all safe, anything else waits for the handlers before it to finish
+        // so its side effects happen in order (RFC 9112 section 9.3.2)
+        let safe = request.method().is_safe();
+        running.retain_mut(|(_, done)| !matches!(done.try_recv(), Err(TryRecvError::Closed)));
+        if !safe || running.iter().any(|(safe, _)| !safe) {
+            for (_, done) in running.drain(..) {
+                let _ = done.await;
+            }
+        }
+        let (done_tx, done_rx) = oneshot::channel();
+        running.push((safe, done_rx));
+
+        let pending = PendingResponse {
+            response: HandlerTask::spawn({
+                let handler = service.handler.clone();
+                async move {
+                    let _done: oneshot::Sender<()> = done_tx;
+                    handler.call(request).await
+                }
+            }),
+            write_timeout: config.write_timeout,
+            version,
+            keep_alive,
+            head_only,
+            expect_continue,
+            body_read,
+        };
+
+        // this waits while the pipeline is full
+        if tx.send(pending).await.is_err() {
+            return Ok(());
+        }
+
+        // the client won't send the body until it gets 100 Continue,
+        // if it didn't get one there's no telling what it'll send next
+        if let Some(send_body) = send_body
+            && !matches!(send_body.await, Ok(true))
+        {
+            return Ok(());
+        }
+
+        // the body has to be read before the next request can be
+        if let Some(body_tx) = body_tx
+            && let Some(body_done) = body_done
+        {
+            if let Err(e) = conn.read_body(framing, body_tx.clone()).await {
+                // the connection can't be used after this since the framing is lost
+                debug!("failed to read request body: {e}");
+                let _ = body_done.send(Err(e.status()));
+                let _ = body_tx.send(Err(e)).await;
+                return Ok(());
+            }
+            let _ = body_done.send(Ok(()));
+        }
+
+        if !keep_alive {
+            return Ok(());
+        }
+    }
+}
+
+/// Queues an error response for a request that couldn't be read
+async fn reject(tx: &mpsc::Sender<PendingResponse>, e: ParseError, write_timeout: Duration) {
+    debug!("rejecting request: {e}");
+
+    if let Some(status) = e.status() {
+        let response = Response::new(status);
+        let _ = tx
+            .send(PendingResponse {
+                response: HandlerTask::spawn(async move { Ok(response) }),
+                write_timeout,
+                version: Version::Http11,
+                keep_alive: false,
+                head_only: false,
+                expect_continue: None,
+                body_read: None,
+            })
+            .await;
+    }
+}
+
+/// Writes a response to the socket
+///
+/// On Linux, file bodies go straight from the file to the socket with
+/// sendfile(2), falling back to copying them through a buffer if the
+/// file can't be sent that way.
+async fn write_response(
+    #[cfg_attr(not(target_os = "linux"), allow(unused_mut))] mut response: Response,
+    stream: &mut OwnedWriteHalf,
+    version: Version,
+    head_only: bool,
+    write_timeout: Duration,
+) -> anyhow::Result<()> {
+    #[cfg(target_os = "linux")]
+    if !head_only && let Some((head, file, offset, length)) = response.take_file(version) {
+        crate::response::write_with_timeout(stream, &head, write_timeout).await?;
+        if !crate::sendfile::send_file(stream.as_ref(), &file, offset, length, write_timeout)
+            .await?
+        {
+            crate::response::copy_file(stream, file, offset, length, write_timeout).await?;
+        }
+        return Ok(());
+    }
+
+    response
+        .write(stream, version, head_only, write_timeout)
+        .await
+}
+
+async fn write_responses(
+    mut stream: OwnedWriteHalf,
+    mut rx: mpsc::Receiver<PendingResponse>,
+    shutdown: watch::Receiver<bool>,
+) -> anyhow::Result<()> {
+    let result = async {
+        while let Some(pending) = rx.recv().await {
+            let mut handler = pending.response;
+            let mut keep_alive = pending.keep_alive;
+
+            let result = match pending.expect_continue {
+                Some(expect) => tokio::select! {
+                    Ok(()) = expect.body_read => {
+                        Response::new(Status::Continue)
+                            .write(&mut stream, pending.version, false, pending.write_timeout)
+                            .await?;
+                        let _ = expect.send_body.send(true);
+                        handler.await
+                    }
+                    // answered without wanting the body
+                    result = &mut handler => {
+                        let _ = expect.send_body.send(false);
+                        keep_alive = false;
+                        result
+                    }
+                },
+                None => handler.await,
+            };
+
+            let (mut response, mut keep_alive) = match result {
+                Ok(Ok(response)) => (response, keep_alive),
+                Ok(Err(e)) => match e.downcast_ref::<ParseError>().and_then(ParseError::status) {
+                    // the handler gave up because the request body was bad
+                    Some(status) => {
+                        debug!("request failed: {e}");
+                        (Response::new(status), false)
+                    }
+                    None => {
+                        error!("request failed: {e}");
+                        (Response::new(Status::InternalServerError), false)
+                    }
+                },
+                Err(e) => {
+                    error!("request handler died: {e}");
+                    (Response::new(Status::InternalServerError), false)
+                }
+            };
+
+            // a response that went out before the body turned out to be bad
+            // would answer a request that was never properly made
+            if let Some(mut body_read) = pending.body_read {
+                let read = match response.body() {
+                    // a streamed response may be waiting on the rest of the body,
+                    // if it's bad after all the connection is closed after it
+                    Body::Reader { .. } | Body::Stream(_) => match body_read.try_recv() {
+                        Err(TryRecvError::Empty) => Ok(()),
+                        read => read.unwrap_or(Err(None)),
+                    },
+                    _ => body_read.await.unwrap_or(Err(None)),
+                };
+
+                if let Err(status) = read {
+                    keep_alive = false;
+                    if let Some(status) = status {
+                        response = Response::new(status);
+                    }
+                }
+            }
+
+            let keep_alive = keep_alive
+                && !*shutdown.borrow()
+                && response.is_delimited(pending.version)
+                && !response.headers().contains_token("Connection", "close");
+            if !keep_alive {
+                response.set_header("Connection", "close");
+            } else if pending.version == Version::Http10 {
+                response.set_header("Connection", "keep-alive");
+            }
+
+            write_response(
+                response,
+                &mut stream,
+                pending.version,
+                pending.head_only,
+                pending.write_timeout,
+            )
+            .await?;
+            stream.flush().await?;
+
+            if !keep_alive {
+                break;
+            }
+        }
+
+        stream.shutdown().await?;
+
+        anyhow::Ok(())
+    }
+    .await;
+
+    // nobody is going to see the responses to anything still queued,
+    // dropping them aborts their handlers
+    rx.close();
+    while rx.try_recv().is_ok() {}
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// Reads a chunked body sent as `input`, returning the data, trailers and result
+    async fn read_chunked(
+        input: &'static [u8],
+        config: ConnectionConfig,
+    ) -> (Vec<u8>, Option<Headers>, Result<(), ParseError>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+
+        client.write_all(input).await.unwrap();
+        client.shutdown().await.unwrap();
+
+        let (read_half, _write_half) = server.into_split();
+        let mut conn = Connection::new(read_half, config);
+        let (tx, mut rx) = mpsc::channel(64);
+        let result = conn.read_body(BodyFraming::Chunked, tx).await;
+
+        let mut data = Vec::new();
+        let mut trailers = None;
+        while let Ok(frame) = rx.try_recv() {
+            match frame.unwrap() {
+                Frame::Data(chunk) => data.extend_from_slice(&chunk),
+                Frame::Trailers(headers) => trailers = Some(headers),
+            }
+        }
+        (data, trailers, result)
+    }
+
+    #[tokio::test]
+    async fn chunked_body() {
+        let (data, trailers, result) = read_chunked(
+            b"5\r\nhello\r\n7;ext=1\r\n, world\r\n0\r\n\r\n",
+            ConnectionConfig::default(),
+        )
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(data, b"hello, world");
+        assert!(trailers.is_none());
+    }
+
+    #[tokio::test]
+    async fn chunked_body_trailers() {
+        let (data, trailers, result) = read_chunked(
+            b"3\r\nabc\r\n0\r\nChecksum: 123\r\nExpires: never\r\n\r\n",
+            ConnectionConfig::default(),
+        )
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(data, b"abc");
+
+        let trailers = trailers.unwrap();
+        assert_eq!(trailers.get("checksum"), Some("123"));
+        assert_eq!(trailers.get("Expires"), Some("never"));
+    }
+
+    #[tokio::test]
+    async fn chunked_body_over_the_limit() {
+        let config = ConnectionConfig {
+            max_body_size: 8,
+            ..ConnectionConfig::default()
+        };
+        let (_, _, result) = read_chunked(b"5\r\nhello\r\n5\r\nworld\r\n0\r\n\r\n", config).await;
+        assert!(matches!(result, Err(ParseError::ContentTooLarge)));
+    }
+
+    #[tokio::test]
+    async fn chunked_body_trailers_over_the_limit() {
+        let config = ConnectionConfig {
+            max_header_size: 16,
+            ..ConnectionConfig::default()
+        };
+        let (_, _, result) =
+            read_chunked(b"0\r\nX-Long: aaaaaaaaaaaaaaaaaaaaaaaaaaaa\r\n\r\n", config).await;
+        assert!(result.is_err());
+
+        let (_, _, result) =
+            read_chunked(b"0\r\nA: 1\r\nB: 2\r\nC: 3\r\nD: 4\r\n\r\n", config).await;
+        assert!(matches!(result, Err(ParseError::HeadersTooLarge)));
+    }
+
+    #[tokio::test]
+    async fn malformed_chunks() {
+        for input in [
+            &b"x\r\nhello\r\n0\r\n\r\n"[..],
+            b"\r\nhello\r\n0\r\n\r\n",
+            b"5\r\nhelloX\r\n0\r\n\r\n",
+            b"11111111111111111\r\n",
+            b"0\r\nBad Name: 1\r\n\r\n",
+        ] {
+            let (_, _, result) = read_chunked(input, ConnectionConfig::default()).await;
+            assert!(
+                matches!(result, Err(ParseError::BadRequest(_))),
+                "{:?}: {result:?}",
+                String::from_utf8_lossy(input)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn handlers_are_aborted_with_the_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+
+        // the handler holds a sender for as long as it runs
+        let (started_tx, mut started) = mpsc::channel(1);
+        let handler = move |_request: Request| {
+            let started = started_tx.clone();
+            async move {
+                started.send(()).await.unwrap();
+                std::future::pending::<Response>().await
+            }
+        };
+        let service = Arc::new(Swap::new(Service {
+            handler: Arc::new(handler),
+            config: ConnectionConfig::default(),
+        }));
+        let (_shutdown_tx, shutdown) = watch::channel(false);
+        let connection = tokio::spawn(handle_connection(server, service, shutdown));
+
+        client
+            .write_all(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n")
+            .await
+            .unwrap();
+        assert_eq!(started.recv().await, Some(()));
+
+        connection.abort();
+        let _ = connection.await;
+        let stopped = timeout(Duration::from_secs(1), started.recv()).await;
+        assert_eq!(stopped, Ok(None), "the handler is still running");
+    }
+
+    /// When each handler started and finished, for requests pipelined on one connection
+    ///
+    /// Requests to /slow take a while to handle.
+    async fn handling_order(requests: &[&str]) -> Vec<String> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let handler = {
+            let events = events.clone();
+            move |request: Request| {
+                let events = events.clone();
+                async move {
+                    let name = format!("{} {}", request.method(), request.path());
+                    events.lock().unwrap().push(format!("start {name}"));
+                    if request.path() == "/slow" {
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                    }
+                    events.lock().unwrap().push(format!("end {name}"));
+                    Status::NoContent
+                }
+            }
+        };
+        let service = Arc::new(Swap::new(Service {
+            handler: Arc::new(handler),
+            config: ConnectionConfig::default(),
+        }));
+        let (_shutdown_tx, shutdown) = watch::channel(false);
+        let connection = tokio::spawn(handle_connection(server, service, shutdown));
+
+        let mut input = String::new();
+        for (i, request) in requests.iter().enumerate() {
+            input.push_str(&format!("{request} HTTP/1.1\r\nHost: a\r\n"));
+            if i == requests.len() - 1 {
+                input.push_str("Connection: close\r\n");
+            }
+            input.push_str("\r\n");
+        }
+        client.write_all(input.as_bytes()).await.unwrap();
+        let mut output = Vec::new();
+        client.read_to_end(&mut output).await.unwrap();
+        connection.await.unwrap().unwrap();
+
+        let events = events.lock().unwrap();
+        events.clone()
+    }
+
+    #[tokio::test]
+    async fn safe_requests_are_handled_together() {
+        assert_eq!(
+            handling_order(&["GET /slow", "GET /fast"]).await,
+            [
+                "start GET /slow",
+                "start GET /fast",
+                "end GET /fast",
+                "end GET /slow",
+            ]
+        );
+    }
+
+    // RFC 9112 section 9.3.2
+    #[tokio::test]
+    async fn unsafe_requests_are_handled_in_order() {
+        assert_eq!(
+            handling_order(&["DELETE /slow", "GET /fast"]).await,
+            [
+                "start DELETE /slow",
+                "end DELETE /slow",
+                "start GET /fast",
+                "end GET /fast",
+            ]
+        );
+        assert_eq!(
+            handling_order(&["GET /slow", "GET /slow", "POST /fast"]).await,
+            [
+                "start GET /slow",
+                "start GET /slow",
+                "end GET /slow",
+                "end GET /slow",
+                "start POST /fast",
+                "end POST /fast",
+            ]
+        );
+        assert_eq!(
+            handling_order(&["POST /slow", "POST /fast"]).await,
+            [
+                "start POST /slow",
+                "end POST /slow",
+                "start POST /fast",
+                "end POST /fast",
+            ]
+        );
+    }
+}
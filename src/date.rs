@@ -0,0 +1,249 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Converts days since the unix epoch to a (year, month, day) civil date
+///
+/// From Howard Hinnant's date algorithms (civil_from_days)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+/// Converts a civil date to days since the unix epoch
+///
+/// From Howard Hinnant's date algorithms (days_from_civil)
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = i64::from((month + 9) % 12);
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Formats a time as an IMF-fixdate (RFC 9110 section 5.6.7)
+///
+/// e.g. Sun, 06 Nov 1994 08:49:37 GMT
+pub fn format_http_date(time: SystemTime) -> String {
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    };
+
+    let days = secs.div_euclid(86400);
+    let secs_of_day = secs.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+
+    // 1970-01-01 was a Thursday
+    let weekday = (days + 4).rem_euclid(7) as usize;
+
+    format!(
+        "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+        DAYS[weekday],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    )
+}
+
+/// Formats a time as an RFC 3339 timestamp in UTC
+///
+/// e.g. 1994-11-06T08:49:37Z
+pub fn format_rfc3339(time: SystemTime) -> String {
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    };
+
+    let secs_of_day = secs.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    )
+}
+
+/// Parses an HTTP-date in any of the three formats (RFC 9110 section 5.6.7)
+///
+/// Senders have to use IMF-fixdate, but the obsolete RFC 850 and asctime
+/// formats still have to be accepted. The day of the week isn't checked.
+pub fn parse_http_date(s: &str) -> Option<SystemTime> {
+    let tokens: Vec<&str> = s.split_whitespace().collect();
+    let (day, month, year, time) = match tokens.as_slice() {
+        // Sun, 06 Nov 1994 08:49:37 GMT
+        [weekday, day, month, year, time, "GMT"] if weekday.ends_with(',') && year.len() == 4 => {
+            (*day, *month, number(year)?, *time)
+        }
+        // Sunday, 06-Nov-94 08:49:37 GMT
+        [weekday, date, time, "GMT"] if weekday.ends_with(',') => {
+            let mut parts = date.split('-');
+            let (day, month, year) = (parts.next()?, parts.next()?, parts.next()?);
+            if parts.next().is_some() || year.len() != 2 {
+                return None;
+            }
+            (day, month, two_digit_year(number(year)?), *time)
+        }
+        // Sun Nov  6 08:49:37 1994
+        [_, month, day, time, year] if year.len() == 4 => (*day, *month, number(year)?, *time),
+        _ => return None,
+    };
+
+    let month = MONTHS.iter().position(|m| *m == month)? as u32 + 1;
+    let day = u32::try_from(number(day)?)
+        .ok()
+        .filter(|day| (1..=31).contains(day))?;
+
+    let mut hms = time.split(':');
+    let (hours, minutes, seconds) = (
+        number(hms.next()?)?,
+        number(hms.next()?)?,
+        number(hms.next()?)?,
+    );
+    if hms.next().is_some() || hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+
+    let secs = days_from_civil(year, month, day) * 86400 + hours * 3600 + minutes * 60 + seconds;
+    match u64::try_from(secs) {
+        Ok(secs) => UNIX_EPOCH.checked_add(Duration::from_secs(secs)),
+        Err(_) => UNIX_EPOCH.checked_sub(Duration::from_secs(secs.unsigned_abs())),
+    }
+}
+
+fn number(s: &str) -> Option<i64> {
+    if s.is_empty() || s.len() > 4 || !s.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// Picks the century for an RFC 850 year
+///
+/// A year that looks more than 50 years in the future is taken to be in
+/// the past (RFC 9110 section 5.6.7).
+fn two_digit_year(year: i64) -> i64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    let (current, _, _) = civil_from_days(now.div_euclid(86400));
+
+    let year = current - current.rem_euclid(100) + year;
+    if year > current + 50 {
+        year - 100
+    } else {
+        year
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sun, 06 Nov 1994 08:49:37 GMT
+    const EXAMPLE: u64 = 784111777;
+
+    fn example() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(EXAMPLE)
+    }
+
+    // the examples from RFC 9110 section 5.6.7
+    #[test]
+    fn rfc_examples() {
+        assert_eq!(
+            parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+            Some(example())
+        );
+        assert_eq!(
+            parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"),
+            Some(example())
+        );
+        assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), Some(example()));
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(format_http_date(example()), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(
+            format_http_date(UNIX_EPOCH),
+            "Thu, 01 Jan 1970 00:00:00 GMT"
+        );
+        assert_eq!(format_rfc3339(example()), "1994-11-06T08:49:37Z");
+
+        let leap_day = UNIX_EPOCH + Duration::from_secs(951782400);
+        assert_eq!(format_http_date(leap_day), "Tue, 29 Feb 2000 00:00:00 GMT");
+        assert_eq!(parse_http_date(&format_http_date(leap_day)), Some(leap_day));
+    }
+
+    #[test]
+    fn rfc_850_years_are_at_most_50_years_ahead() {
+        let (current, _, _) = civil_from_days(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs() as i64
+                / 86400,
+        );
+        let year = |date: &str| {
+            let secs = parse_http_date(date)
+                .unwrap()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            civil_from_days(secs as i64 / 86400).0
+        };
+
+        let ahead = (current + 50) % 100;
+        assert_eq!(
+            year(&format!("Monday, 01-Jan-{ahead:02} 00:00:00 GMT")),
+            current + 50
+        );
+        let behind = (current + 51) % 100;
+        assert_eq!(
+            year(&format!("Monday, 01-Jan-{behind:02} 00:00:00 GMT")),
+            current + 51 - 100
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        for date in [
+            "",
+            "Sun, 06 Nov 1994 08:49:37",
+            "Sun, 06 Nov 1994 08:49:37 UTC",
+            "Sun, 06 Nov 94 08:49:37 GMT",
+            "Sun, 06 Foo 1994 08:49:37 GMT",
+            "Sun, 32 Nov 1994 08:49:37 GMT",
+            "Sun, 06 Nov 1994 24:00:00 GMT",
+            "Sun, 06 Nov 1994 08:60:37 GMT",
+            "Sun, 06 Nov 1994 08:49 GMT",
+            "Sun, 06 Nov 1994 08:49:37:00 GMT",
+            "Sun, +6 Nov 1994 08:49:37 GMT",
+            "Sunday, 06-Nov-1994 08:49:37 GMT",
+            "Sunday, 06-Nov-94-1 08:49:37 GMT",
+            "Sun Nov  6 08:49:37 94",
+            "1994-11-06T08:49:37Z",
+        ] {
+            assert_eq!(parse_http_date(date), None, "{date}");
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use crate::Status;
+
+/// Reasons a request (head or body) couldn't be read off the connection
+#[derive(Debug)]
+pub enum ParseError {
+    /// Malformed request-line or header section
+    BadRequest(&'static str),
+
+    /// Well-formed HTTP-version that isn't HTTP/1.x
+    UnsupportedVersion(String),
+
+    UriTooLong,
+    HeadersTooLarge,
+    ContentTooLarge,
+
+    /// Transfer-Encoding other than chunked
+    UnsupportedTransferCoding,
+
+    /// Expect other than 100-continue
+    ExpectationFailed,
+
+    /// The client started a request but didn't finish it in time
+    Timeout,
+
+    /// The client closed the connection partway through a request
+    Closed,
+
+    Io(std::io::Error),
+}
+
+impl ParseError {
+    /// The status to answer with, if the client can still be answered
+    pub fn status(&self) -> Option<Status> {
+        match self {
+            ParseError::BadRequest(_) => Some(Status::BadRequest),
+            ParseError::UnsupportedVersion(_) => Some(Status::HttpVersionNotSupported),
+            ParseError::UriTooLong => Some(Status::UriTooLong),
+            ParseError::HeadersTooLarge => Some(Status::RequestHeaderFieldsTooLarge),
+            ParseError::ContentTooLarge => Some(Status::ContentTooLarge),
+            ParseError::UnsupportedTransferCoding => Some(Status::NotImplemented),
+            ParseError::ExpectationFailed => Some(Status::ExpectationFailed),
+            ParseError::Timeout => Some(Status::RequestTimeout),
+            ParseError::Closed | ParseError::Io(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::BadRequest(reason) => write!(f, "bad request: {reason}"),
+            ParseError::UnsupportedVersion(version) => {
+                write!(f, "unsupported version: {version}")
+            }
+            ParseError::UriTooLong => write!(f, "request-target too long"),
+            ParseError::HeadersTooLarge => write!(f, "request head too large"),
+            ParseError::ContentTooLarge => write!(f, "request body too large"),
+            ParseError::UnsupportedTransferCoding => write!(f, "unsupported transfer coding"),
+            ParseError::ExpectationFailed => write!(f, "unsupported expectation"),
+            ParseError::Timeout => write!(f, "read timeout"),
+            ParseError::Closed => write!(f, "connection closed mid-request"),
+            ParseError::Io(e) => write!(f, "read error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ParseError {
+    fn from(e: std::io::Error) -> Self {
+        ParseError::Io(e)
+    }
+}
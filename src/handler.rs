@@ -0,0 +1,56 @@
+use std::{future::Future, pin::Pin, sync::Arc};
+
+use crate::{Request, Response, Status};
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Application logic that turns a request into a response
+///
+/// Implemented for any `async fn(Request) -> R` (or closure returning a
+/// future) where R is a Response, a Status or an anyhow::Result<Response>.
+/// An error result is answered with a 500 unless it came from reading a
+/// bad request body, which gets the matching 4xx.
+pub trait Handler: Send + Sync + 'static {
+    fn call(&self, request: Request) -> BoxFuture<'_, anyhow::Result<Response>>;
+}
+
+impl<F, Fut, R> Handler for F
+where
+    F: Fn(Request) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = R> + Send + 'static,
+    R: IntoResponse,
+{
+    fn call(&self, request: Request) -> BoxFuture<'_, anyhow::Result<Response>> {
+        let fut = self(request);
+        Box::pin(async move { fut.await.into_response() })
+    }
+}
+
+impl<H: Handler + ?Sized> Handler for Arc<H> {
+    fn call(&self, request: Request) -> BoxFuture<'_, anyhow::Result<Response>> {
+        (**self).call(request)
+    }
+}
+
+/// Things a handler can return
+pub trait IntoResponse {
+    fn into_response(self) -> anyhow::Result<Response>;
+}
+
+impl IntoResponse for Response {
+    fn into_response(self) -> anyhow::Result<Response> {
+        Ok(self)
+    }
+}
+
+impl IntoResponse for Status {
+    fn into_response(self) -> anyhow::Result<Response> {
+        Ok(Response::new(self))
+    }
+}
+
+impl IntoResponse for anyhow::Result<Response> {
+    fn into_response(self) -> anyhow::Result<Response> {
+        self
+    }
+}
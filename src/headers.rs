@@ -0,0 +1,216 @@
+use crate::ParseError;
+
+/// Ordered, case-insensitive header multimap
+///
+/// Field names keep the case they were given in, lookups ignore it.
+/// Repeated fields are kept as separate entries in the order received.
+#[derive(Debug, Clone, Default)]
+pub struct Headers {
+    entries: Vec<(String, String)>,
+}
+
+impl Headers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the first value for the given field name
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns every value for the given field name, in the order received
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Checks a comma-separated list field (like Connection) for a token,
+    /// ignoring case and looking across repeated fields
+    pub fn contains_token(&self, name: &str, token: &str) -> bool {
+        self.get_all(name)
+            .flat_map(|v| v.split(','))
+            .any(|t| t.trim().eq_ignore_ascii_case(token))
+    }
+
+    /// Replaces every value for the field name, returning the first old value
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<String>) -> Option<String> {
+        let name = name.into();
+        let old = self.remove(&name);
+        self.entries.push((name, value.into()));
+        old
+    }
+
+    /// Adds a value without touching existing values for the same field name
+    pub fn append(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.entries.push((name.into(), value.into()));
+    }
+
+    /// Removes every value for the field name, returning the first one
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        let mut old = None;
+        self.entries.retain_mut(|(n, v)| {
+            if !n.eq_ignore_ascii_case(name) {
+                return true;
+            }
+
+            if old.is_none() {
+                old = Some(std::mem::take(v));
+            }
+            false
+        });
+        old
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+}
+
+/// tchar from RFC 9110 section 5.6.2
+pub fn is_tchar(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&c)
+}
+
+pub fn is_token(s: &[u8]) -> bool {
+    !s.is_empty() && s.iter().copied().all(is_tchar)
+}
+
+/// Whether a field value is safe to send, no control characters but HTAB
+///
+/// A CR or LF would end the field early and let the rest of the value
+/// through as fields of its own (RFC 9110 section 5.5).
+pub fn is_field_value(s: &[u8]) -> bool {
+    !s.iter().any(|&c| c.is_ascii_control() && c != b'\t')
+}
+
+/// Trims optional whitespace (SP / HTAB) from both ends
+pub fn trim_ows(mut s: &[u8]) -> &[u8] {
+    while let [b' ' | b'\t', rest @ ..] = s {
+        s = rest;
+    }
+    while let [rest @ .., b' ' | b'\t'] = s {
+        s = rest;
+    }
+    s
+}
+
+/// Parses a single field-line (without the trailing CRLF)
+///
+/// field-line = field-name ":" OWS field-value OWS (RFC 9112 section 5)
+pub fn parse_field(line: &[u8]) -> Result<(String, String), ParseError> {
+    // obs-fold is a continuation of the previous line, which we reject
+    // rather than try to unfold (RFC 9112 section 5.2)
+    if line.first().is_some_and(|&c| c == b' ' || c == b'\t') {
+        return Err(ParseError::BadRequest("obsolete line folding"));
+    }
+
+    let colon = line
+        .iter()
+        .position(|&c| c == b':')
+        .ok_or(ParseError::BadRequest("missing header separator"))?;
+
+    // this also rejects whitespace between the name and the colon
+    let name = &line[..colon];
+    if !is_token(name) {
+        return Err(ParseError::BadRequest("invalid header name"));
+    }
+
+    let value = trim_ows(&line[colon + 1..]);
+    if !is_field_value(value) {
+        return Err(ParseError::BadRequest("invalid header value"));
+    }
+
+    let value =
+        str::from_utf8(value).map_err(|_| ParseError::BadRequest("invalid header value"))?;
+
+    // the name is all tchar so it's always valid ascii
+    let name = str::from_utf8(name).unwrap_or_default();
+
+    Ok((name.to_string(), value.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(line: &[u8]) -> Option<(String, String)> {
+        parse_field(line).ok()
+    }
+
+    #[test]
+    fn fields() {
+        let field = |name: &str, value: &str| Some((name.to_string(), value.to_string()));
+        assert_eq!(parsed(b"Host: example.com"), field("Host", "example.com"));
+        assert_eq!(parsed(b"Host:example.com"), field("Host", "example.com"));
+        assert_eq!(parsed(b"X-Empty:"), field("X-Empty", ""));
+        assert_eq!(parsed(b"X-Ows: \t a  b \t "), field("X-Ows", "a  b"));
+        assert_eq!(parsed(b"X-Tab: a\tb"), field("X-Tab", "a\tb"));
+        assert_eq!(parsed(b"X-Colon: a:b"), field("X-Colon", "a:b"));
+        assert_eq!(
+            parsed(b"!#$%&'*+-.^_`|~09az: v"),
+            field("!#$%&'*+-.^_`|~09az", "v")
+        );
+        assert_eq!(
+            parsed("X-Utf8: h\u{e9}".as_bytes()),
+            field("X-Utf8", "h\u{e9}")
+        );
+    }
+
+    // RFC 9112 section 5.2
+    #[test]
+    fn obs_fold_is_rejected() {
+        assert!(parse_field(b" continued").is_err());
+        assert!(parse_field(b"\tcontinued").is_err());
+    }
+
+    // RFC 9112 section 5.1, whitespace before the colon has to be rejected
+    #[test]
+    fn names_are_tokens() {
+        assert!(parse_field(b"Host : example.com").is_err());
+        assert!(parse_field(b"Host\t: example.com").is_err());
+        assert!(parse_field(b": no name").is_err());
+        assert!(parse_field(b"no colon").is_err());
+        assert!(parse_field(b"X(y): v").is_err());
+        assert!(parse_field(b"X/y: v").is_err());
+        assert!(parse_field(b"X\"y\": v").is_err());
+        assert!(parse_field("X-\u{e9}: v".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn values_have_no_control_characters() {
+        assert!(parse_field(b"X: a\rb").is_err());
+        assert!(parse_field(b"X: a\nb").is_err());
+        assert!(parse_field(b"X: a\0b").is_err());
+        assert!(parse_field(b"X: a\x7fb").is_err());
+        assert!(parse_field(b"X: a\x1bb").is_err());
+        assert!(parse_field(b"X: \xff").is_err());
+    }
+
+    #[test]
+    fn repeated_fields_keep_their_order() {
+        let mut headers = Headers::new();
+        headers.append("Accept", "a");
+        headers.append("Other", "x");
+        headers.append("accept", "b");
+        assert_eq!(headers.get_all("ACCEPT").collect::<Vec<_>>(), ["a", "b"]);
+        assert_eq!(headers.get("Accept"), Some("a"));
+    }
+}
@@ -0,0 +1,83 @@
+//! A small HTTP/1.1 server on top of Tokio
+//!
+//! ```no_run
+//! use webserver::{Request, Response, Router, Server, Status};
+//!
+//! async fn index(_request: Request) -> Response {
+//!     Response::new(Status::Ok)
+//! }
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! Server::builder()
+//!     .bind("0.0.0.0:8080")
+//!     .router(Router::new().get("/", index))
+//!     .serve()
+//!     .await
+//! # }
+//! ```
+
+mod autoindex;
+mod body;
+mod conditional;
+mod config;
+mod connection;
+mod date;
+mod error;
+mod extensions;
+mod handler;
+mod headers;
+mod limits;
+mod method;
+mod middleware;
+mod mime;
+mod range;
+mod request;
+mod response;
+mod router;
+#[cfg(target_os = "linux")]
+mod sendfile;
+mod server;
+mod static_files;
+mod status;
+mod swap;
+mod toml;
+mod uri;
+mod version;
+
+pub use body::{Body, BodySender, Frame, RequestBody};
+pub use conditional::Preconditions;
+pub use config::{
+    Config, ConfigError, ListenerConfig, LoggingConfig, RouteAction, RouteConfig, StaticConfig,
+};
+pub use connection::ConnectionConfig;
+pub use error::ParseError;
+pub use extensions::Extensions;
+pub use handler::{BoxFuture, Handler, IntoResponse};
+pub use headers::Headers;
+pub use limits::raise_open_files_limit;
+pub use method::{Method, allow_header};
+pub use middleware::{Conditional, Logger, Middleware, Next, Pipeline, Timeout};
+pub use request::Request;
+pub use response::Response;
+pub use router::Router;
+pub use server::{Listener, Server, ServerBuilder, ServerHandle};
+pub use static_files::{StaticFiles, Symlinks};
+pub use status::Status;
+pub use version::Version;
+
+use body::BodyFraming;
+use request::parse_request;
+
+const SERVER_NAME: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+const READ_TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_millis(500);
+const WRITE_TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_millis(500);
+const MAX_HEADER_SIZE: usize = 1024 * 8;
+const MAX_URI_LENGTH: usize = 1024 * 4;
+const IDLE_TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_secs(5);
+const HANDLER_TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_secs(30);
+const DRAIN_TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_secs(10);
+const MAX_REQUESTS_PER_CONNECTION: usize = 100;
+const PIPELINE_DEPTH: usize = 16;
+const WRITE_CHUNK_SIZE: usize = 1024 * 64;
+const MAX_BODY_SIZE: u64 = 1024 * 1024 * 8;
@@ -0,0 +1,43 @@
+use std::io;
+
+/// Raises the limit on open files (RLIMIT_NOFILE) to `wanted`, or as close as the hard limit allows
+///
+/// Every connection takes a file descriptor, and the usual soft limit of
+/// 1024 runs out long before anything else does. The limit is never
+/// lowered. Returns what the limit is afterwards.
+#[cfg(unix)]
+#[allow(clippy::useless_conversion)] // from rlim_t, which isn't always u64
+pub fn raise_open_files_limit(wanted: u64) -> io::Result<u64> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+
+    // SAFETY: getrlimit only writes to the struct it's given
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // rlim_t is u64 on most targets but c_ulong on some (Android, 32-bit
+    // glibc), where anything too big for it is as good as unlimited
+    let wanted = libc::rlim_t::try_from(wanted).unwrap_or(libc::rlim_t::MAX);
+    let raised = wanted.min(limit.rlim_max);
+    if raised <= limit.rlim_cur {
+        return Ok(u64::from(limit.rlim_cur));
+    }
+
+    limit.rlim_cur = raised;
+    // SAFETY: setrlimit only reads the struct it's given
+    if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(u64::from(raised))
+}
+
+#[cfg(not(unix))]
+pub fn raise_open_files_limit(_wanted: u64) -> io::Result<u64> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "the open files limit can only be changed on unix",
+    ))
+}
@@ -1,188 +1,241 @@
-use std::collections::HashMap;
+use std::{net::SocketAddr, sync::Arc};
 
-use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::{TcpListener, TcpStream},
-    time::timeout,
-};
-use tracing::{Level, info};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{info, warn};
 use tracing_subscriber::FmtSubscriber;
 
-const READ_TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_millis(500);
-const WRITE_TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_millis(500);
-const MAX_HEADER_SIZE: usize = 1024 * 8;
+use webserver::{
+    Conditional, Config, Listener, ListenerConfig, Logger, LoggingConfig, Request, Response,
+    Router, Server, ServerBuilder, ServerHandle, Status, Timeout, raise_open_files_limit,
+};
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, strum::Display)]
-pub enum Method {
-    Get,
+const DEFAULT_LISTEN: &str = "0.0.0.0:8080";
+
+const USAGE: &str = "\
+usage: webserver [options]
+
+options:
+  -c, --config FILE          read settings from FILE
+      --check-config         check the settings and print them without serving
+  -l, --listen ADDR          listen on ADDR, can be repeated
+      --admin-listen ADDR    serve the admin endpoints on ADDR, can be repeated
+  -h, --help                 show this message
+
+ADDR is an IP address and port, e.g. 0.0.0.0:8080 or [::1]:8080.
+Addresses given as options replace the config file's listeners. The
+WEBSERVER_CONFIG, WEBSERVER_LISTEN and WEBSERVER_ADMIN_LISTEN environment
+variables are used for options that aren't given, with commas separating
+multiple addresses.
+
+SIGHUP or a POST to /reload on an admin listener reloads the config file.
+Changes to the listener addresses or logging need a restart.";
+
+/// Asks the main task to reload the config, and gets told how it went
+type ReloadRequest = oneshot::Sender<Result<(), String>>;
+
+/// Command line options
+#[derive(Debug, Default)]
+struct Args {
+    config: Option<String>,
+    check_config: bool,
+    listen: Vec<SocketAddr>,
+    admin_listen: Vec<SocketAddr>,
 }
 
-impl TryFrom<&str> for Method {
-    type Error = anyhow::Error;
-
-    fn try_from(value: &str) -> Result<Self, Self::Error> {
-        match value {
-            "GET" => Ok(Method::Get),
-            m => anyhow::bail!("unsupported method: {m}"),
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> anyhow::Result<Self> {
+        let mut parsed = Args::default();
+        while let Some(arg) = args.next() {
+            // --flag=value is the same as --flag value
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+                _ => (arg.as_str(), None),
+            };
+
+            let mut value = || {
+                inline
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| anyhow::anyhow!("{flag} needs a value"))
+            };
+
+            match flag {
+                "-c" | "--config" => parsed.config = Some(value()?),
+                "--check-config" => parsed.check_config = true,
+                "-l" | "--listen" => parsed.listen.push(address(flag, &value()?)?),
+                "--admin-listen" => parsed.admin_listen.push(address(flag, &value()?)?),
+                "-h" | "--help" => {
+                    println!("{USAGE}");
+                    std::process::exit(0);
+                }
+                _ => anyhow::bail!("unknown option {arg}\n\n{USAGE}"),
+            }
         }
-    }
-}
 
-#[derive(Debug, Clone)]
-pub struct Request {
-    method: Method,
-    path: String,
-    headers: HashMap<String, String>,
-}
+        parsed.fill_from_env()?;
+        Ok(parsed)
+    }
 
-impl Request {
-    pub fn new(method: Method, path: String) -> Self {
-        Self {
-            method,
-            path,
-            headers: HashMap::new(),
+    fn fill_from_env(&mut self) -> anyhow::Result<()> {
+        let list = |name| -> anyhow::Result<Vec<SocketAddr>> {
+            let Ok(value) = std::env::var(name) else {
+                return Ok(Vec::new());
+            };
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|addr| !addr.is_empty())
+                .map(|addr| address(name, addr))
+                .collect()
+        };
+
+        if self.config.is_none() {
+            self.config = std::env::var("WEBSERVER_CONFIG").ok();
         }
+        if self.listen.is_empty() {
+            self.listen = list("WEBSERVER_LISTEN")?;
+        }
+        if self.admin_listen.is_empty() {
+            self.admin_listen = list("WEBSERVER_ADMIN_LISTEN")?;
+        }
+        Ok(())
     }
 
-    pub fn set_header(&mut self, name: String, value: String) -> Option<String> {
-        self.headers.insert(name, value)
+    /// Replaces the config file's listeners with any given as options
+    fn apply(&self, config: &mut Config) {
+        let listener = |address: &str, admin| ListenerConfig {
+            address: address.to_string(),
+            admin,
+            connection: config.connection,
+        };
+
+        if !self.listen.is_empty() || !self.admin_listen.is_empty() {
+            let listen = self
+                .listen
+                .iter()
+                .map(|addr| listener(&addr.to_string(), false));
+            let admin = self
+                .admin_listen
+                .iter()
+                .map(|addr| listener(&addr.to_string(), true));
+            config.listeners = listen.chain(admin).collect();
+        } else if config.listeners.is_empty() {
+            config.listeners = vec![listener(DEFAULT_LISTEN, false)];
+        }
     }
 }
 
-fn next_line_break(buf: &[u8]) -> Option<usize> {
-    if buf.len() < 2 {
-        return None;
-    }
-
-    let mut idx = 0;
-    loop {
-        if idx >= buf.len() - 1 {
-            return None;
-        }
-
-        if buf[idx] == '\r' as u8 && buf[idx + 1] == '\n' as u8 {
-            return Some(idx);
-        }
+/// An address to listen on, checked now rather than when it's bound
+fn address(source: &str, value: &str) -> anyhow::Result<SocketAddr> {
+    value
+        .parse()
+        .map_err(|e| anyhow::anyhow!("invalid address {value:?} for {source}: {e}"))
+}
 
-        idx += 1;
-    }
+async fn index(_request: Request) -> Response {
+    Response::new(Status::Ok)
 }
 
-async fn read_request(stream: &mut TcpStream) -> anyhow::Result<Request> {
-    let mut buf = [0; MAX_HEADER_SIZE];
-    let n = match timeout(READ_TIMEOUT, stream.read(&mut buf)).await {
-        Ok(Ok(n)) => n,
-        Ok(Err(e)) => Err(e)?,
-        Err(_) => anyhow::bail!("read timeout"),
-    };
+async fn health(_request: Request) -> Response {
+    let mut response = Response::new(Status::Ok);
+    response.set_header("Content-Type", "text/plain");
+    response.set_body("ok\n");
+    response
+}
 
-    let line = match next_line_break(&buf[..n]) {
-        Some(idx) => str::from_utf8(&buf[..idx])?,
-        None => anyhow::bail!("too much"),
+async fn reload(request: Request) -> anyhow::Result<Response> {
+    let Some(reload_tx) = request.state::<mpsc::Sender<ReloadRequest>>() else {
+        anyhow::bail!("admin router is missing its reload sender");
     };
 
-    let mut parts = line.split_whitespace();
-
-    let method: Method = parts
-        .next()
-        .ok_or(anyhow::anyhow!("missing method"))
-        .and_then(TryInto::try_into)?;
-
-    let path: String = parts
-        .next()
-        .ok_or(anyhow::anyhow!("missing path"))
-        .map(Into::into)?;
-
-    let mut request = Request::new(method, path);
-
-    /*loop {
-        line_buffer.clear();
-        stream.read_line(&mut line_buffer).await?;
+    let (reply_tx, reply_rx) = oneshot::channel();
+    reload_tx.send(reply_tx).await?;
 
-        if line_buffer.is_empty() || line_buffer == "\n" || line_buffer == "\r\n" {
-            break;
-        }
-
-        let mut comps = line_buffer.split(":");
-        let key = comps.next().ok_or(anyhow::anyhow!("missing header name"))?;
-        let value = comps
-            .next()
-            .ok_or(anyhow::anyhow!("missing header value"))?
-            .trim();
-
-        request.set_header(key.to_string(), value.to_string());
-    }*/
-
-    Ok(request)
-}
-
-#[derive(Debug, Copy, Clone, PartialEq, Eq, strum::Display)]
-pub enum Status {
-    #[strum(serialize = "200 OK")]
-    Ok,
+    let (status, body) = match reply_rx.await? {
+        Ok(()) => (Status::Ok, "reloaded\n".to_string()),
+        Err(e) => (
+            Status::InternalServerError,
+            format!("reload failed, keeping the old config: {e}\n"),
+        ),
+    };
 
-    #[strum(serialize = "200 OK")]
-    NotFound,
+    let mut response = Response::new(status);
+    response.set_header("Content-Type", "text/plain");
+    response.set_body(body);
+    Ok(response)
 }
 
-#[derive(Debug, Clone)]
-pub struct Response {
-    status: Status,
-    headers: HashMap<String, String>,
+fn admin_router(reload_tx: mpsc::Sender<ReloadRequest>) -> Router {
+    Router::new()
+        .get("/health", health)
+        .post("/reload", reload)
+        .with_state(Arc::new(reload_tx))
 }
 
-impl Response {
-    pub fn new(status: Status) -> Self {
-        Self {
-            status,
-            headers: HashMap::new(),
-        }
-    }
-
-    pub fn set_header(&mut self, name: String, value: String) -> Option<String> {
-        self.headers.insert(name, value)
-    }
+/// Everything the server serves, built the same way at startup and on reload
+fn server(config: &Config, reload_tx: &mpsc::Sender<ReloadRequest>) -> ServerBuilder {
+    // the placeholder index page is only there until something is configured
+    let router = if config.routes.is_empty() && config.statics.is_empty() {
+        Router::new().get("/", index)
+    } else {
+        config.router()
+    };
 
-    pub async fn write(mut self, stream: &mut TcpStream) -> anyhow::Result<()> {
-        match timeout(
-            WRITE_TIMEOUT,
-            stream.write_all(format!("HTTP/1.1 {}\r\n\r\n", self.status).as_bytes()),
-        )
-        .await
-        {
-            Ok(Ok(_)) => (),
-            Ok(Err(e)) => Err(e)?,
-            Err(_) => anyhow::bail!("write timeout"),
+    let mut server = Server::builder()
+        .router(router)
+        .layer(Logger)
+        .layer(Conditional)
+        .layer(Timeout(config.handler_timeout))
+        .drain_timeout(config.drain_timeout);
+
+    for listener in &config.listeners {
+        let mut builder = Listener::new(&listener.address).config(listener.connection);
+        if listener.admin {
+            builder = builder.router(admin_router(reload_tx.clone()));
         }
-
-        //tokio::io::copy(&mut self.data, stream).await?;
-
-        Ok(())
+        server = server.listener(builder);
     }
+    server
 }
 
-async fn handle_request(request: Request) -> anyhow::Result<Response> {
-    info!("request: {:?}", request);
-
-    let response = Response::new(Status::Ok);
+/// Rereads the config file, keeping the running config if it isn't valid
+fn reload_config(
+    args: &Args,
+    handle: &ServerHandle,
+    reload_tx: &mpsc::Sender<ReloadRequest>,
+) -> anyhow::Result<()> {
+    let Some(path) = &args.config else {
+        anyhow::bail!("there's no config file to reload");
+    };
 
-    info!("response: {:?}", response);
-    Ok(response)
+    let mut config = Config::load(path)?;
+    args.apply(&mut config);
+    handle.reload(server(&config, reload_tx))
 }
 
-async fn handle_connection(mut stream: TcpStream) -> anyhow::Result<()> {
-    let request = read_request(&mut stream).await?;
-    let response = handle_request(request).await?;
-
-    response.write(&mut stream).await?;
-    stream.flush().await?;
-
+/// Turns each SIGHUP into a reload request
+#[cfg(unix)]
+fn reload_on_hangup(reload_tx: mpsc::Sender<ReloadRequest>) -> anyhow::Result<()> {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut hangup = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            info!("reloading config on SIGHUP");
+            let (reply_tx, reply_rx) = oneshot::channel();
+            if reload_tx.send(reply_tx).await.is_err() {
+                break;
+            }
+            let _ = reply_rx.await;
+        }
+    });
     Ok(())
 }
 
-fn init_logging() -> anyhow::Result<()> {
+fn init_logging(logging: &LoggingConfig) -> anyhow::Result<()> {
     let subscriber = FmtSubscriber::builder()
-        .with_max_level(Level::INFO)
+        .with_max_level(logging.level)
+        .with_ansi(logging.color)
         .finish();
 
     tracing::subscriber::set_global_default(subscriber)?;
@@ -190,25 +243,85 @@ fn init_logging() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Shuts down gracefully on SIGTERM or SIGINT, or right away on a second one
+fn shutdown_on_signal(handle: ServerHandle) -> anyhow::Result<()> {
+    #[cfg(unix)]
+    let mut terminate = {
+        use tokio::signal::unix::{SignalKind, signal};
+        signal(SignalKind::terminate())?
+    };
+
+    tokio::spawn(async move {
+        for signals in 0.. {
+            #[cfg(unix)]
+            tokio::select! {
+                _ = terminate.recv() => (),
+                _ = tokio::signal::ctrl_c() => (),
+            }
+            #[cfg(not(unix))]
+            let _ = tokio::signal::ctrl_c().await;
+
+            if signals > 0 {
+                warn!("exiting without waiting for connections");
+                std::process::exit(1);
+            }
+
+            info!("shutting down");
+            handle.shutdown();
+        }
+    });
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    init_logging()?;
+    let args = Args::parse(std::env::args().skip(1))?;
+    let mut config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    args.apply(&mut config);
+
+    if args.check_config {
+        print!("{config}");
+        return Ok(());
+    }
 
-    // TODO: configurable address / port
-    let listener = TcpListener::bind("0.0.0.0:8080").await?;
-    info!("listening at {}", listener.local_addr()?);
+    init_logging(&config.logging)?;
+
+    // not being able to is worth a warning, but the server still works
+    if let Some(open_files) = config.open_files {
+        match raise_open_files_limit(open_files) {
+            Ok(limit) if limit < open_files => {
+                warn!("open files limit is {limit}, the hard limit is below {open_files}")
+            }
+            Ok(limit) => info!("open files limit is {limit}"),
+            Err(e) => warn!("can't raise the open files limit to {open_files}: {e}"),
+        }
+    }
+
+    let (reload_tx, mut reload_rx) = mpsc::channel::<ReloadRequest>(1);
+    #[cfg(unix)]
+    reload_on_hangup(reload_tx.clone())?;
+
+    let server = server(&config, &reload_tx).build().await?;
+    let handle = server.handle();
+    shutdown_on_signal(handle.clone())?;
+
+    let serve = server.serve();
+    tokio::pin!(serve);
 
     loop {
-        let (stream, addr) = listener.accept().await?;
-        info!("new connection from {addr}");
-
-        tokio::spawn(async move {
-            match handle_connection(stream).await {
-                Ok(_) => {}
-                Err(e) => {
-                    println!("error: {e}");
+        tokio::select! {
+            result = &mut serve => return result,
+            Some(reply) = reload_rx.recv() => {
+                let result = reload_config(&args, &handle, &reload_tx);
+                match &result {
+                    Ok(()) => info!("config reloaded"),
+                    Err(e) => warn!("config reload failed, keeping the old config: {e}"),
                 }
+                let _ = reply.send(result.map_err(|e| e.to_string()));
             }
-        });
+        }
     }
 }
@@ -0,0 +1,60 @@
+use crate::{ParseError, headers};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, strum::Display)]
+#[strum(serialize_all = "UPPERCASE")]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Patch,
+    Options,
+    Trace,
+    Connect,
+
+    /// Any other method token, which is case-sensitive
+    #[strum(to_string = "{0}")]
+    Extension(String),
+}
+
+impl TryFrom<&str> for Method {
+    type Error = ParseError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "GET" => Ok(Method::Get),
+            "HEAD" => Ok(Method::Head),
+            "POST" => Ok(Method::Post),
+            "PUT" => Ok(Method::Put),
+            "DELETE" => Ok(Method::Delete),
+            "PATCH" => Ok(Method::Patch),
+            "OPTIONS" => Ok(Method::Options),
+            "TRACE" => Ok(Method::Trace),
+            "CONNECT" => Ok(Method::Connect),
+            m if headers::is_token(m.as_bytes()) => Ok(Method::Extension(m.to_string())),
+            _ => Err(ParseError::BadRequest("invalid method")),
+        }
+    }
+}
+
+impl Method {
+    /// Whether the method is only for reading (RFC 9110 section 9.2.1)
+    ///
+    /// Extension methods aren't known to be safe, so they count as unsafe.
+    pub fn is_safe(&self) -> bool {
+        matches!(
+            self,
+            Method::Get | Method::Head | Method::Options | Method::Trace
+        )
+    }
+}
+
+/// Formats an Allow header value from a list of methods
+pub fn allow_header(methods: &[Method]) -> String {
+    methods
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
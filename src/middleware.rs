@@ -0,0 +1,176 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use tracing::{info, warn};
+
+use crate::{BoxFuture, Handler, Method, Preconditions, Request, Response, Status};
+
+/// Behaviour that wraps every request, like logging or auth
+///
+/// A middleware can change the request before passing it on with
+/// `next.run(request)`, answer it itself without calling `next`, or change
+/// the response on the way back out.
+///
+/// Implemented for any `async fn(Request, Next) -> anyhow::Result<Response>`.
+pub trait Middleware: Send + Sync + 'static {
+    fn handle(&self, request: Request, next: Next) -> BoxFuture<'_, anyhow::Result<Response>>;
+}
+
+impl<F, Fut> Middleware for F
+where
+    F: Fn(Request, Next) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = anyhow::Result<Response>> + Send + 'static,
+{
+    fn handle(&self, request: Request, next: Next) -> BoxFuture<'_, anyhow::Result<Response>> {
+        Box::pin(self(request, next))
+    }
+}
+
+/// The rest of the pipeline after the current middleware
+pub struct Next {
+    layers: Arc<[Arc<dyn Middleware>]>,
+    index: usize,
+    handler: Arc<dyn Handler>,
+}
+
+impl Next {
+    /// Passes the request to the next middleware, or the handler if this was the last
+    pub async fn run(self, request: Request) -> anyhow::Result<Response> {
+        match self.layers.get(self.index).cloned() {
+            Some(layer) => {
+                let next = Next {
+                    index: self.index + 1,
+                    ..self
+                };
+                layer.handle(request, next).await
+            }
+            None => self.handler.call(request).await,
+        }
+    }
+}
+
+/// A handler with middleware around it
+///
+/// Layers run in the order they're added, so the first one added sees the
+/// request first and the response last.
+#[derive(Clone)]
+pub struct Pipeline {
+    layers: Vec<Arc<dyn Middleware>>,
+    handler: Arc<dyn Handler>,
+}
+
+impl Pipeline {
+    pub fn new(handler: impl Handler) -> Self {
+        Self {
+            layers: Vec::new(),
+            handler: Arc::new(handler),
+        }
+    }
+
+    /// Replaces the handler at the end of the pipeline
+    pub fn handler(mut self, handler: impl Handler) -> Self {
+        self.handler = Arc::new(handler);
+        self
+    }
+
+    pub fn layer(mut self, middleware: impl Middleware) -> Self {
+        self.layers.push(Arc::new(middleware));
+        self
+    }
+
+    /// Freezes the layers into a handler that can be shared between connections
+    pub fn build(self) -> Arc<dyn Handler> {
+        if self.layers.is_empty() {
+            return self.handler;
+        }
+
+        Arc::new(Layered {
+            layers: self.layers.into(),
+            handler: self.handler,
+        })
+    }
+}
+
+struct Layered {
+    layers: Arc<[Arc<dyn Middleware>]>,
+    handler: Arc<dyn Handler>,
+}
+
+impl Handler for Layered {
+    fn call(&self, request: Request) -> BoxFuture<'_, anyhow::Result<Response>> {
+        let next = Next {
+            layers: self.layers.clone(),
+            index: 0,
+            handler: self.handler.clone(),
+        };
+        Box::pin(next.run(request))
+    }
+}
+
+/// Logs each request and its response
+pub struct Logger;
+
+impl Middleware for Logger {
+    fn handle(&self, request: Request, next: Next) -> BoxFuture<'_, anyhow::Result<Response>> {
+        Box::pin(async move {
+            info!(
+                "request: {} {} ({} headers)",
+                request.method(),
+                request.path(),
+                request.headers().len()
+            );
+
+            let result = next.run(request).await;
+            match &result {
+                Ok(response) if response.status().is_server_error() => {
+                    warn!("response: {:?}", response)
+                }
+                Ok(response) => info!("response: {:?}", response),
+                Err(e) => warn!("handler failed: {e}"),
+            }
+            result
+        })
+    }
+}
+
+/// Answers GET and HEAD requests with 304 Not Modified or 412 Precondition
+/// Failed when their conditional headers say to
+///
+/// Works from the ETag and Last-Modified on the handler's response, so any
+/// handler that sets them gets conditional requests for free. Other methods
+/// are passed through, since by the time there's a response the change
+/// has already been made; handlers for those check `Preconditions` first.
+pub struct Conditional;
+
+impl Middleware for Conditional {
+    fn handle(&self, request: Request, next: Next) -> BoxFuture<'_, anyhow::Result<Response>> {
+        Box::pin(async move {
+            if !matches!(request.method(), Method::Get | Method::Head) {
+                return next.run(request).await;
+            }
+
+            let preconditions = Preconditions::from_request(&request);
+            let response = next.run(request).await?;
+            Ok(preconditions.apply(response))
+        })
+    }
+}
+
+/// Answers with 503 Service Unavailable if the rest of the pipeline takes too long
+///
+/// Only covers producing the response, a streamed body can take longer.
+pub struct Timeout(pub Duration);
+
+impl Middleware for Timeout {
+    fn handle(&self, request: Request, next: Next) -> BoxFuture<'_, anyhow::Result<Response>> {
+        let duration = self.0;
+        Box::pin(async move {
+            match tokio::time::timeout(duration, next.run(request)).await {
+                Ok(result) => result,
+                Err(_) => {
+                    warn!("handler timed out after {duration:?}");
+                    Ok(Response::new(Status::ServiceUnavailable))
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,226 @@
+use std::ops::Range;
+
+/// The most ranges served in one response, asking for more gets the whole thing
+const MAX_RANGES: usize = 16;
+
+/// What a Range header asks for (RFC 9110 section 14.2)
+#[derive(Debug, PartialEq, Eq)]
+pub enum Ranges {
+    /// Nothing we can use, so the whole representation is sent
+    Ignore,
+
+    /// Sorted byte ranges, with any that overlap or touch merged
+    Satisfiable(Vec<Range<u64>>),
+
+    /// None of the ranges overlap the representation
+    Unsatisfiable,
+}
+
+/// Works out which bytes of a representation `length` long a Range header asks for
+///
+/// Units other than bytes and anything malformed are ignored, which the
+/// RFC allows, rather than rejected.
+pub fn parse_range(value: &str, length: u64) -> Ranges {
+    let Some((unit, specs)) = value.trim().split_once('=') else {
+        return Ranges::Ignore;
+    };
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return Ranges::Ignore;
+    }
+
+    let mut ranges = Vec::new();
+    let mut any = false;
+    for spec in specs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        any = true;
+        let Some((first, last)) = spec.split_once('-') else {
+            return Ranges::Ignore;
+        };
+
+        let range = match (number(first), number(last)) {
+            // the last n bytes
+            (None, Some(suffix)) if first.is_empty() => length.saturating_sub(suffix)..length,
+            (Some(first), None) if last.is_empty() => first..length,
+            (Some(first), Some(last)) if first <= last => first..last.saturating_add(1).min(length),
+            _ => return Ranges::Ignore,
+        };
+
+        if range.start < range.end {
+            ranges.push(range);
+        }
+    }
+
+    if !any {
+        return Ranges::Ignore;
+    }
+    if ranges.is_empty() {
+        return Ranges::Unsatisfiable;
+    }
+
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<Range<u64>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+
+    // lots of little ranges cost more to send than the whole thing
+    if merged.len() > MAX_RANGES {
+        return Ranges::Ignore;
+    }
+    Ranges::Satisfiable(merged)
+}
+
+fn number(s: &str) -> Option<u64> {
+    if s.is_empty() || !s.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// The Content-Range value for part of a representation (RFC 9110 section 14.4)
+pub fn content_range(range: &Range<u64>, length: u64) -> String {
+    format!("bytes {}-{}/{length}", range.start, range.end - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Start and end pairs, since a one element array of ranges looks like a mistake
+    fn satisfiable(ranges: &[(u64, u64)]) -> Ranges {
+        Ranges::Satisfiable(ranges.iter().map(|&(start, end)| start..end).collect())
+    }
+
+    // the examples from RFC 9110 section 14.1.2, for a 10000 byte representation
+    #[test]
+    fn rfc_examples() {
+        assert_eq!(parse_range("bytes=0-499", 10000), satisfiable(&[(0, 500)]));
+        assert_eq!(
+            parse_range("bytes=500-999", 10000),
+            satisfiable(&[(500, 1000)])
+        );
+        assert_eq!(
+            parse_range("bytes=-500", 10000),
+            satisfiable(&[(9500, 10000)])
+        );
+        assert_eq!(
+            parse_range("bytes=9500-", 10000),
+            satisfiable(&[(9500, 10000)])
+        );
+        assert_eq!(
+            parse_range("bytes=0-0,-1", 10000),
+            satisfiable(&[(0, 1), (9999, 10000)])
+        );
+        assert_eq!(
+            parse_range("bytes= 0-999, 4500-5499, -1000", 10000),
+            satisfiable(&[(0, 1000), (4500, 5500), (9000, 10000)])
+        );
+        assert_eq!(
+            parse_range("bytes=500-600,601-999", 10000),
+            satisfiable(&[(500, 1000)])
+        );
+        assert_eq!(
+            parse_range("bytes=500-700,601-999", 10000),
+            satisfiable(&[(500, 1000)])
+        );
+    }
+
+    #[test]
+    fn clamped_to_the_length() {
+        assert_eq!(
+            parse_range("bytes=0-999999", 10000),
+            satisfiable(&[(0, 10000)])
+        );
+        assert_eq!(
+            parse_range("bytes=-20000", 10000),
+            satisfiable(&[(0, 10000)])
+        );
+        assert_eq!(
+            parse_range("bytes=9000-18446744073709551615", 10000),
+            satisfiable(&[(9000, 10000)])
+        );
+    }
+
+    #[test]
+    fn sorted_and_merged() {
+        assert_eq!(
+            parse_range("bytes=50-59,0-9,5-14", 100),
+            satisfiable(&[(0, 15), (50, 60)])
+        );
+        assert_eq!(parse_range("bytes=0-9,-95", 100), satisfiable(&[(0, 100)]));
+    }
+
+    #[test]
+    fn unsatisfiable() {
+        assert_eq!(parse_range("bytes=10000-", 10000), Ranges::Unsatisfiable);
+        assert_eq!(
+            parse_range("bytes=10000-10999", 10000),
+            Ranges::Unsatisfiable
+        );
+        assert_eq!(parse_range("bytes=-0", 10000), Ranges::Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-", 0), Ranges::Unsatisfiable);
+        assert_eq!(
+            parse_range("bytes=20000-,30000-30010", 10000),
+            Ranges::Unsatisfiable
+        );
+
+        // one that fits is enough
+        assert_eq!(
+            parse_range("bytes=20000-,0-0", 10000),
+            satisfiable(&[(0, 1)])
+        );
+    }
+
+    #[test]
+    fn malformed_is_ignored() {
+        for value in [
+            "",
+            "bytes",
+            "bytes=",
+            "bytes=,",
+            "items=0-1",
+            "bytes=abc",
+            "bytes=1",
+            "bytes=5-1",
+            "bytes=-",
+            "bytes=0-1,x",
+            "bytes=+1-2",
+            "bytes=1--2",
+            "bytes=0x1-2",
+        ] {
+            assert_eq!(parse_range(value, 10000), Ranges::Ignore, "{value:?}");
+        }
+        assert_eq!(parse_range("BYTES=0-1", 10000), satisfiable(&[(0, 2)]));
+    }
+
+    #[test]
+    fn too_many_ranges() {
+        let ranges = |n: u64| {
+            let specs: Vec<String> = (0..n).map(|i| format!("{}-{}", i * 10, i * 10)).collect();
+            format!("bytes={}", specs.join(","))
+        };
+        assert!(matches!(
+            parse_range(&ranges(MAX_RANGES as u64), 10000),
+            Ranges::Satisfiable(ranges) if ranges.len() == MAX_RANGES
+        ));
+        assert_eq!(
+            parse_range(&ranges(MAX_RANGES as u64 + 1), 10000),
+            Ranges::Ignore
+        );
+
+        // what counts is what's left after merging
+        let overlapping = format!("bytes={}", vec!["0-9"; 100].join(","));
+        assert_eq!(parse_range(&overlapping, 10000), satisfiable(&[(0, 10)]));
+    }
+
+    #[test]
+    fn content_range_value() {
+        assert_eq!(content_range(&(0..500), 10000), "bytes 0-499/10000");
+        assert_eq!(
+            content_range(&(9999..10000), 10000),
+            "bytes 9999-9999/10000"
+        );
+    }
+}
@@ -0,0 +1,332 @@
+use std::sync::Arc;
+
+use crate::{Extensions, Headers, Method, ParseError, RequestBody, Version, headers, uri};
+
+#[derive(Debug)]
+pub struct Request {
+    method: Method,
+    path: String,
+    query: Option<String>,
+    version: Version,
+    headers: Headers,
+    params: Vec<(String, String)>,
+    extensions: Extensions,
+    body: RequestBody,
+}
+
+impl Request {
+    pub fn new(method: Method, path: String, version: Version) -> Self {
+        Self {
+            method,
+            path,
+            query: None,
+            version,
+            headers: Headers::new(),
+            params: Vec::new(),
+            extensions: Extensions::new(),
+            body: RequestBody::empty(),
+        }
+    }
+
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// The request path, still percent-encoded and without the query
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn query(&self) -> Option<&str> {
+        self.query.as_deref()
+    }
+
+    /// A decoded path parameter captured by the router
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn set_param(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.params.push((name.into(), value.into()));
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Whether the client is waiting for 100 Continue before sending the body
+    ///
+    /// HTTP/1.0 clients can't understand interim responses so it's ignored
+    /// for them (RFC 9110 section 10.1.1).
+    pub fn expects_continue(&self) -> Result<bool, ParseError> {
+        let Some(expect) = self.headers.get("Expect") else {
+            return Ok(false);
+        };
+
+        if self.version == Version::Http10 {
+            return Ok(false);
+        }
+
+        if expect.trim().eq_ignore_ascii_case("100-continue") {
+            Ok(true)
+        } else {
+            Err(ParseError::ExpectationFailed)
+        }
+    }
+
+    /// Whether the client wants the connection kept open after this request
+    ///
+    /// HTTP/1.1 defaults to persistent connections, HTTP/1.0 has to ask for it
+    pub fn keep_alive(&self) -> bool {
+        if self.headers.contains_token("Connection", "close") {
+            return false;
+        }
+
+        match self.version {
+            Version::Http10 => self.headers.contains_token("Connection", "keep-alive"),
+            Version::Http11 => true,
+        }
+    }
+
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    pub fn set_header(
+        &mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Option<String> {
+        self.headers.insert(name, value)
+    }
+
+    pub fn append_header(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.headers.append(name, value)
+    }
+
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
+
+    /// Shared state added with `Router::with_state`
+    pub fn state<S: Send + Sync + 'static>(&self) -> Option<Arc<S>> {
+        self.extensions.get::<Arc<S>>().cloned()
+    }
+
+    pub fn body_mut(&mut self) -> &mut RequestBody {
+        &mut self.body
+    }
+
+    pub fn set_body(&mut self, body: RequestBody) {
+        self.body = body;
+    }
+
+    /// Takes the body, leaving an empty one behind
+    pub fn take_body(&mut self) -> RequestBody {
+        std::mem::take(&mut self.body)
+    }
+}
+
+fn next_line_break(buf: &[u8]) -> Option<usize> {
+    if buf.len() < 2 {
+        return None;
+    }
+
+    let mut idx = 0;
+    loop {
+        if idx >= buf.len() - 1 {
+            return None;
+        }
+
+        if buf[idx] == b'\r' && buf[idx + 1] == b'\n' {
+            return Some(idx);
+        }
+
+        idx += 1;
+    }
+}
+
+/// Parses a complete request head, including the terminating empty line
+pub(crate) fn parse_request(buf: &[u8], max_uri_length: usize) -> Result<Request, ParseError> {
+    let (line, mut pos) = match next_line_break(buf) {
+        Some(idx) => (
+            str::from_utf8(&buf[..idx])
+                .map_err(|_| ParseError::BadRequest("invalid request line"))?,
+            idx + 2,
+        ),
+        None => return Err(ParseError::BadRequest("incomplete request line")),
+    };
+
+    let mut parts = line.split_whitespace();
+
+    let method: Method = parts
+        .next()
+        .ok_or(ParseError::BadRequest("missing method"))
+        .and_then(TryInto::try_into)?;
+
+    let target = parts.next().ok_or(ParseError::BadRequest("missing path"))?;
+    if target.len() > max_uri_length {
+        return Err(ParseError::UriTooLong);
+    }
+    let (path, query) = uri::split_target(target)?;
+
+    let version: Version = parts
+        .next()
+        .ok_or(ParseError::BadRequest("missing version"))
+        .and_then(TryInto::try_into)?;
+
+    if parts.next().is_some() {
+        return Err(ParseError::BadRequest("invalid request line"));
+    }
+
+    let mut request = Request::new(method, path, version);
+    request.query = query;
+
+    loop {
+        let line = match next_line_break(&buf[pos..]) {
+            Some(idx) => &buf[pos..pos + idx],
+            None => return Err(ParseError::BadRequest("incomplete request head")),
+        };
+        pos += line.len() + 2;
+
+        if line.is_empty() {
+            break;
+        }
+
+        let (name, value) = headers::parse_field(line)?;
+        request.append_header(name, value);
+    }
+
+    // HTTP/1.1 requests need exactly one Host (RFC 9112 section 3.2)
+    match request.headers().get_all("Host").count() {
+        0 if version == Version::Http11 => return Err(ParseError::BadRequest("missing host")),
+        0 | 1 => (),
+        _ => return Err(ParseError::BadRequest("multiple host headers")),
+    }
+
+    Ok(request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(head: &str) -> Result<Request, ParseError> {
+        parse_request(head.as_bytes(), 8192)
+    }
+
+    /// The message of the BadRequest that `head` gets
+    fn bad_request(head: &str) -> &'static str {
+        match parse(head) {
+            Err(ParseError::BadRequest(message)) => message,
+            result => panic!("{head:?} got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn request_line() {
+        let request = parse("GET /a/b?x=1 HTTP/1.1\r\nHost: a\r\n\r\n").unwrap();
+        assert_eq!(request.method(), &Method::Get);
+        assert_eq!(request.path(), "/a/b");
+        assert_eq!(request.query(), Some("x=1"));
+        assert_eq!(request.version(), Version::Http11);
+
+        let request = parse("PURGE http://a/b HTTP/1.0\r\n\r\n").unwrap();
+        assert_eq!(request.method(), &Method::Extension("PURGE".into()));
+        assert_eq!(request.path(), "/b");
+        assert_eq!(request.version(), Version::Http10);
+    }
+
+    #[test]
+    fn bad_request_lines() {
+        assert_eq!(
+            bad_request("GET / HTTP/1.1 extra\r\nHost: a\r\n\r\n"),
+            "invalid request line"
+        );
+        assert_eq!(bad_request("GET\r\nHost: a\r\n\r\n"), "missing path");
+        assert_eq!(bad_request("GET /\r\nHost: a\r\n\r\n"), "missing version");
+        assert_eq!(bad_request("\r\n\r\n"), "missing method");
+        assert_eq!(
+            bad_request("G(T / HTTP/1.1\r\nHost: a\r\n\r\n"),
+            "invalid method"
+        );
+        assert_eq!(
+            bad_request("GET a HTTP/1.1\r\nHost: a\r\n\r\n"),
+            "invalid request target"
+        );
+        assert_eq!(bad_request("GET / HTTP/1.1"), "incomplete request line");
+        assert_eq!(
+            bad_request("GET / HTTP/1.1\r\nHost: a\r\n"),
+            "incomplete request head"
+        );
+        assert!(matches!(
+            parse("GET / HTTP/2.0\r\nHost: a\r\n\r\n"),
+            Err(ParseError::UnsupportedVersion(_))
+        ));
+        assert!(matches!(
+            parse_request(b"GET /0123456789 HTTP/1.1\r\nHost: a\r\n\r\n", 10),
+            Err(ParseError::UriTooLong)
+        ));
+    }
+
+    #[test]
+    fn fields() {
+        let request = parse(
+            "GET / HTTP/1.1\r\nHost: a\r\nAccept: text/html \r\nX-Other: x\r\naccept:\t*/*\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(request.header("host"), Some("a"));
+        assert_eq!(
+            request.headers().get_all("Accept").collect::<Vec<_>>(),
+            ["text/html", "*/*"]
+        );
+        assert_eq!(request.headers().len(), 4);
+    }
+
+    #[test]
+    fn bad_fields() {
+        assert_eq!(
+            bad_request("GET / HTTP/1.1\r\nHost: a\r\nX-A: b\r\n c\r\n\r\n"),
+            "obsolete line folding"
+        );
+        assert_eq!(
+            bad_request("GET / HTTP/1.1\r\nHost : a\r\n\r\n"),
+            "invalid header name"
+        );
+        assert_eq!(
+            bad_request("GET / HTTP/1.1\r\nHost: a\r\nX-A: b\nX-B: c\r\n\r\n"),
+            "invalid header value"
+        );
+        assert_eq!(
+            bad_request("GET / HTTP/1.1\r\nHost: a\r\nX-A: b\rc\r\n\r\n"),
+            "invalid header value"
+        );
+    }
+
+    // RFC 9112 section 3.2
+    #[test]
+    fn host() {
+        assert_eq!(bad_request("GET / HTTP/1.1\r\n\r\n"), "missing host");
+        assert_eq!(
+            bad_request("GET / HTTP/1.1\r\nHost: a\r\nhost: b\r\n\r\n"),
+            "multiple host headers"
+        );
+        assert_eq!(
+            bad_request("GET / HTTP/1.0\r\nHost: a\r\nHost: a\r\n\r\n"),
+            "multiple host headers"
+        );
+        assert!(parse("GET / HTTP/1.0\r\n\r\n").is_ok());
+        assert!(parse("GET / HTTP/1.1\r\nHost:\r\n\r\n").is_ok());
+    }
+}
@@ -0,0 +1,406 @@
+use std::{io::SeekFrom, time::SystemTime};
+
+use tokio::{
+    fs::File,
+    io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt},
+    time::{Duration, timeout},
+};
+
+use tracing::{error, warn};
+
+use crate::{
+    Body, Frame, Headers, SERVER_NAME, Status, Version, WRITE_CHUNK_SIZE, date,
+    headers::{is_field_value, is_token},
+};
+
+#[derive(Debug)]
+pub struct Response {
+    status: Status,
+    headers: Headers,
+    body: Body,
+}
+
+impl Response {
+    pub fn new(status: Status) -> Self {
+        Self {
+            status,
+            headers: Headers::new(),
+            body: Body::Empty,
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    pub fn set_header(
+        &mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Option<String> {
+        self.headers.insert(name, value)
+    }
+
+    pub fn body(&self) -> &Body {
+        &self.body
+    }
+
+    pub fn set_body(&mut self, body: impl Into<Body>) {
+        self.body = body.into();
+    }
+
+    /// Whether the status forbids a body (RFC 9110 sections 15.2, 15.3.5 and 15.4.5)
+    fn is_bodiless(&self) -> bool {
+        matches!(self.status.code(), 100..=199 | 204 | 304)
+    }
+
+    /// Whether the client can find the end of the response
+    /// without the connection being closed
+    pub fn is_delimited(&self, version: Version) -> bool {
+        self.is_bodiless() || self.body.length().is_some() || version == Version::Http11
+    }
+
+    /// Writes the response, leaving off the body for HEAD requests
+    ///
+    /// Bodies without a known length are sent chunked to HTTP/1.1 clients
+    /// and delimited by closing the connection for HTTP/1.0 clients. Each
+    /// write to the stream has to finish within the write timeout.
+    pub async fn write<W>(
+        mut self,
+        stream: &mut W,
+        version: Version,
+        omit_body: bool,
+        write_timeout: Duration,
+    ) -> anyhow::Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let (mut buf, chunked) = self.head(version);
+        if omit_body || self.is_bodiless() {
+            return write_with_timeout(stream, &buf, write_timeout).await;
+        }
+        match self.body {
+            Body::Empty => write_with_timeout(stream, &buf, write_timeout).await,
+            Body::Bytes(bytes) => {
+                // one write so the head doesn't go out in its own packet
+                buf.extend_from_slice(&bytes);
+                write_with_timeout(stream, &buf, write_timeout).await
+            }
+            Body::Reader { reader, length } => {
+                write_with_timeout(stream, &buf, write_timeout).await?;
+
+                let writer = BodyWriter {
+                    stream,
+                    chunked,
+                    write_timeout,
+                };
+                copy(writer, reader, length).await
+            }
+            Body::File {
+                file,
+                offset,
+                length,
+            } => {
+                write_with_timeout(stream, &buf, write_timeout).await?;
+                copy_file(stream, file, offset, length, write_timeout).await
+            }
+            Body::Stream(mut rx) => {
+                write_with_timeout(stream, &buf, write_timeout).await?;
+
+                let mut writer = BodyWriter {
+                    stream,
+                    chunked,
+                    write_timeout,
+                };
+                let mut trailers = None;
+                while let Some(frame) = rx.recv().await {
+                    match frame {
+                        Frame::Data(data) => writer.write(&data).await?,
+                        Frame::Trailers(headers) => {
+                            trailers = Some(headers);
+                            break;
+                        }
+                    }
+                }
+
+                writer.finish(trailers).await
+            }
+        }
+    }
+
+    /// Takes a file body to be sent some other way, e.g. with sendfile(2)
+    ///
+    /// Returns the head to send ahead of it, along with the file and the
+    /// part of it to send. None, leaving the response as it was, for
+    /// anything without a file body or a status that allows one.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn take_file(&mut self, version: Version) -> Option<(Vec<u8>, File, u64, u64)> {
+        if self.is_bodiless() || !matches!(self.body, Body::File { .. }) {
+            return None;
+        }
+
+        let (head, _) = self.head(version);
+        match std::mem::take(&mut self.body) {
+            Body::File {
+                file,
+                offset,
+                length,
+            } => Some((head, file, offset, length)),
+            // it's been swapped for a 500 that can't be sent as it was
+            body => {
+                self.body = body;
+                None
+            }
+        }
+    }
+
+    /// The status line and header fields, with Date, Server and the body's framing filled in
+    ///
+    /// Also says whether the body is to be sent chunked. A response that
+    /// can't be sent as it is gets turned into a 500.
+    fn head(&mut self, version: Version) -> (Vec<u8>, bool) {
+        // a Custom status made without from_code can be any number
+        if Status::from_code(self.status.code()).is_none() {
+            error!(
+                "invalid status code {}, sending 500 instead",
+                self.status.code()
+            );
+            *self = Response::new(Status::InternalServerError);
+        }
+
+        // a CR or LF in a field would let whoever set it add fields of their own
+        let invalid = self
+            .headers
+            .iter()
+            .find(|&(name, value)| !is_valid_field(name, value))
+            .map(|(name, _)| name.to_string());
+        if let Some(name) = invalid {
+            error!("invalid response header field {name:?}, sending 500 instead");
+            *self = Response::new(Status::InternalServerError);
+        }
+
+        // interim responses are kept bare
+        if !self.status.is_informational() {
+            if !self.headers.contains("Date") {
+                self.set_header("Date", date::format_http_date(SystemTime::now()));
+            }
+
+            if !self.headers.contains("Server") {
+                self.set_header("Server", SERVER_NAME);
+            }
+        }
+
+        let mut chunked = false;
+        if self.is_bodiless() {
+            // 304 can describe the selected representation, the rest can't
+            if self.status.code() != 304 {
+                self.headers.remove("Content-Length");
+            }
+        } else if let Some(length) = self.body.length() {
+            self.headers.remove("Transfer-Encoding");
+            self.set_header("Content-Length", length.to_string());
+        } else if version == Version::Http11 {
+            self.headers.remove("Content-Length");
+            self.set_header("Transfer-Encoding", "chunked");
+            chunked = true;
+        } else {
+            // no length means the body ends when the connection does
+            self.set_header("Connection", "close");
+        }
+
+        // the reason phrase is optional but the space before it isn't
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\n",
+            self.status.code(),
+            self.status.reason().unwrap_or_default()
+        );
+        for (name, value) in self.headers.iter() {
+            head.push_str(name);
+            head.push_str(": ");
+            head.push_str(value);
+            head.push_str("\r\n");
+        }
+        head.push_str("\r\n");
+
+        (head.into_bytes(), chunked)
+    }
+}
+
+/// Copies part of a file a buffer at a time
+pub(crate) async fn copy_file<W>(
+    stream: &mut W,
+    mut file: File,
+    offset: u64,
+    length: u64,
+    write_timeout: Duration,
+) -> anyhow::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    file.seek(SeekFrom::Start(offset)).await?;
+    let writer = BodyWriter {
+        stream,
+        chunked: false,
+        write_timeout,
+    };
+    copy(writer, file.take(length), Some(length)).await
+}
+
+/// Copies a body from a reader a buffer at a time
+async fn copy<W, R>(
+    mut writer: BodyWriter<'_, W>,
+    mut reader: R,
+    length: Option<u64>,
+) -> anyhow::Result<()>
+where
+    W: AsyncWrite + Unpin,
+    R: AsyncRead + Unpin,
+{
+    let mut buf = vec![0; WRITE_CHUNK_SIZE];
+    let mut written = 0;
+    loop {
+        // never write more than the Content-Length we sent, or
+        // wait on a reader that has nothing more to give
+        let max = match length {
+            Some(length) if written == length => break,
+            Some(length) => buf.len().min((length - written) as usize),
+            None => buf.len(),
+        };
+
+        let n = reader.read(&mut buf[..max]).await?;
+        if n == 0 {
+            break;
+        }
+
+        writer.write(&buf[..n]).await?;
+        written += n as u64;
+    }
+
+    if let Some(length) = length
+        && written != length
+    {
+        anyhow::bail!("body was {written} bytes, expected {length}");
+    }
+
+    writer.finish(None).await
+}
+
+/// Writes body data either as-is or with chunked framing (RFC 9112 section 7.1)
+struct BodyWriter<'a, W> {
+    stream: &'a mut W,
+    chunked: bool,
+    write_timeout: Duration,
+}
+
+impl<W> BodyWriter<'_, W>
+where
+    W: AsyncWrite + Unpin,
+{
+    async fn write(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        if !self.chunked {
+            return write_with_timeout(self.stream, data, self.write_timeout).await;
+        }
+
+        // an empty chunk would end the body early
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let mut buf = format!("{:x}\r\n", data.len()).into_bytes();
+        buf.extend_from_slice(data);
+        buf.extend_from_slice(b"\r\n");
+        write_with_timeout(self.stream, &buf, self.write_timeout).await
+    }
+
+    /// Ends the body, trailers are dropped if it isn't chunked
+    async fn finish(self, trailers: Option<Headers>) -> anyhow::Result<()> {
+        if !self.chunked {
+            return Ok(());
+        }
+
+        let mut buf = String::from("0\r\n");
+        for (name, value) in trailers.iter().flat_map(Headers::iter) {
+            // too late for a 500, so the field is just left off
+            if !is_valid_field(name, value) {
+                warn!("dropping invalid trailer field {name:?}");
+                continue;
+            }
+
+            buf.push_str(name);
+            buf.push_str(": ");
+            buf.push_str(value);
+            buf.push_str("\r\n");
+        }
+        buf.push_str("\r\n");
+
+        write_with_timeout(self.stream, buf.as_bytes(), self.write_timeout).await
+    }
+}
+
+fn is_valid_field(name: &str, value: &str) -> bool {
+    is_token(name.as_bytes()) && is_field_value(value.as_bytes())
+}
+
+pub(crate) async fn write_with_timeout<W>(
+    stream: &mut W,
+    buf: &[u8],
+    write_timeout: Duration,
+) -> anyhow::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    match timeout(write_timeout, stream.write_all(buf)).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => Err(e)?,
+        Err(_) => anyhow::bail!("write timeout"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn written(response: Response) -> String {
+        let mut out = Vec::new();
+        response
+            .write(&mut out, Version::Http11, false, Duration::from_secs(1))
+            .await
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[tokio::test]
+    async fn bodiless_statuses_go_by_code() {
+        for code in [103, 204, 304] {
+            let mut response = Response::new(Status::Custom(code));
+            response.set_body("test");
+
+            let out = written(response).await;
+            assert!(out.ends_with("\r\n\r\n"), "{code} sent a body: {out:?}");
+            assert!(!out.contains("Content-Length: 4"), "{code}: {out:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn invalid_fields_are_not_sent() {
+        let mut response = Response::new(Status::Found);
+        response.set_header("Location", "/new\r\nSet-Cookie: pwned=1");
+        let out = written(response).await;
+        assert!(out.starts_with("HTTP/1.1 500 "));
+        assert!(!out.contains("Set-Cookie"));
+
+        let mut response = Response::new(Status::Ok);
+        response.set_header("Bad Name", "value");
+        assert!(written(response).await.starts_with("HTTP/1.1 500 "));
+    }
+
+    #[tokio::test]
+    async fn invalid_status_codes_are_not_sent() {
+        let out = written(Response::new(Status::Custom(42))).await;
+        assert!(out.starts_with("HTTP/1.1 500 "));
+    }
+}
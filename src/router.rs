@@ -0,0 +1,599 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{BoxFuture, Extensions, Handler, Method, Request, Response, Status, allow_header, uri};
+
+type BoxHandler = Arc<dyn Handler>;
+
+/// Adds a piece of shared state to a request's extensions
+type StateFn = Arc<dyn Fn(&mut Extensions) + Send + Sync>;
+
+struct Route {
+    method: Method,
+    pattern: String,
+    handler: BoxHandler,
+}
+
+/// Handlers for each method on a single path pattern
+#[derive(Default)]
+struct Endpoint {
+    handlers: Vec<(Method, BoxHandler)>,
+}
+
+impl Endpoint {
+    fn get(&self, method: &Method) -> Option<&BoxHandler> {
+        self.handlers
+            .iter()
+            .find(|(m, _)| m == method)
+            .map(|(_, handler)| handler)
+    }
+
+    fn allowed(&self) -> Vec<Method> {
+        with_implied_methods(self.handlers.iter().map(|(m, _)| m.clone()).collect())
+    }
+}
+
+/// Adds the methods we answer ourselves for the Allow header
+fn with_implied_methods(mut methods: Vec<Method>) -> Vec<Method> {
+    if methods.contains(&Method::Get) && !methods.contains(&Method::Head) {
+        methods.push(Method::Head);
+    }
+    if !methods.contains(&Method::Options) {
+        methods.push(Method::Options);
+    }
+    methods
+}
+
+/// One path segment's worth of the routing tree
+#[derive(Default)]
+struct Node {
+    endpoint: Option<Endpoint>,
+    literals: HashMap<String, Node>,
+    param: Option<(String, Box<Node>)>,
+    wildcard: Option<(String, Endpoint)>,
+}
+
+impl Node {
+    fn insert(
+        &mut self,
+        segments: &[&str],
+        method: Method,
+        handler: BoxHandler,
+        pattern: &str,
+    ) -> Result<(), String> {
+        let endpoint = match segments {
+            [] => self.endpoint.get_or_insert_with(Endpoint::default),
+            [segment, rest @ ..] => {
+                if let Some(name) = segment.strip_prefix('*') {
+                    if !rest.is_empty() {
+                        return Err(format!("wildcard must be last in {pattern}"));
+                    }
+
+                    let (existing, endpoint) = self
+                        .wildcard
+                        .get_or_insert_with(|| (name.to_string(), Endpoint::default()));
+                    if existing != name {
+                        return Err(format!("conflicting wildcard names in {pattern}"));
+                    }
+                    endpoint
+                } else if let Some(name) = segment.strip_prefix(':') {
+                    let (existing, node) = self
+                        .param
+                        .get_or_insert_with(|| (name.to_string(), Box::default()));
+                    if existing != name {
+                        return Err(format!("conflicting parameter names in {pattern}"));
+                    }
+                    return node.insert(rest, method, handler, pattern);
+                } else {
+                    return self
+                        .literals
+                        .entry(segment.to_string())
+                        .or_default()
+                        .insert(rest, method, handler, pattern);
+                }
+            }
+        };
+
+        if endpoint.get(&method).is_some() {
+            return Err(format!("duplicate route {method} {pattern}"));
+        }
+        endpoint.handlers.push((method, handler));
+        Ok(())
+    }
+
+    /// Finds the endpoint for the path segments, preferring literal segments
+    /// over parameters over wildcards
+    fn find<'a>(
+        &'a self,
+        segments: &[&str],
+        params: &mut Vec<(String, String)>,
+    ) -> Option<&'a Endpoint> {
+        match segments {
+            // a trailing slash matches the same as without it
+            [] | [""] if self.endpoint.is_some() => return self.endpoint.as_ref(),
+            [] => (),
+            [segment, rest @ ..] => {
+                if let Some(endpoint) = self
+                    .literals
+                    .get(*segment)
+                    .and_then(|node| node.find(rest, params))
+                {
+                    return Some(endpoint);
+                }
+
+                if let Some((name, node)) = &self.param
+                    && !segment.is_empty()
+                    && let Some(value) = uri::percent_decode(segment)
+                {
+                    params.push((name.clone(), value));
+                    if let Some(endpoint) = node.find(rest, params) {
+                        return Some(endpoint);
+                    }
+                    params.pop();
+                }
+            }
+        }
+
+        let (name, endpoint) = self.wildcard.as_ref()?;
+        params.push((name.clone(), uri::percent_decode(&segments.join("/"))?));
+        Some(endpoint)
+    }
+}
+
+fn split_path(path: &str) -> Vec<&str> {
+    match path.strip_prefix('/') {
+        Some("") => vec![],
+        Some(path) => path.split('/').collect(),
+        None => vec![path],
+    }
+}
+
+/// Maps method and path patterns to handlers
+///
+/// Patterns are made of literal segments, `:name` parameters that match a
+/// single segment and a trailing `*name` wildcard that matches the rest of
+/// the path, e.g. `/users/:id` or `/static/*path`. Matched parameters are
+/// available from `Request::param`.
+///
+/// Paths that don't match anything get a 404, paths that match but not for
+/// the request method get a 405. HEAD falls back to the GET handler and
+/// OPTIONS is answered automatically.
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+    root: Node,
+    states: Vec<StateFn>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a handler for the method and path pattern
+    ///
+    /// Panics if the pattern is invalid or already has a handler for the method.
+    pub fn route(self, method: Method, pattern: &str, handler: impl Handler) -> Self {
+        self.try_route(method, pattern, handler)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Adds a handler like `route`, failing instead of panicking
+    pub fn try_route(
+        mut self,
+        method: Method,
+        pattern: &str,
+        handler: impl Handler,
+    ) -> Result<Self, String> {
+        self.add(Route {
+            method,
+            pattern: pattern.to_string(),
+            handler: Arc::new(handler),
+        })?;
+        Ok(self)
+    }
+
+    pub fn get(self, pattern: &str, handler: impl Handler) -> Self {
+        self.route(Method::Get, pattern, handler)
+    }
+
+    pub fn post(self, pattern: &str, handler: impl Handler) -> Self {
+        self.route(Method::Post, pattern, handler)
+    }
+
+    pub fn put(self, pattern: &str, handler: impl Handler) -> Self {
+        self.route(Method::Put, pattern, handler)
+    }
+
+    pub fn delete(self, pattern: &str, handler: impl Handler) -> Self {
+        self.route(Method::Delete, pattern, handler)
+    }
+
+    /// Shares state with this router's handlers through `Request::state`
+    ///
+    /// Each type of state can be added once, adding it again replaces it.
+    pub fn with_state<S: Send + Sync + 'static>(mut self, state: Arc<S>) -> Self {
+        self.states
+            .push(Arc::new(move |extensions: &mut Extensions| {
+                extensions.insert(state.clone());
+            }));
+        self
+    }
+
+    /// Adds all of another router's routes under a path prefix
+    ///
+    /// The mounted routes keep any state given to the router they came from.
+    pub fn mount(mut self, prefix: &str, router: Router) -> Self {
+        let prefix = prefix.trim_end_matches('/');
+        for route in router.routes {
+            let pattern = match route.pattern.as_str() {
+                "/" if !prefix.is_empty() => prefix.to_string(),
+                pattern => format!("{prefix}{pattern}"),
+            };
+
+            let handler: BoxHandler = if router.states.is_empty() {
+                route.handler
+            } else {
+                Arc::new(WithState {
+                    states: router.states.clone(),
+                    inner: route.handler,
+                })
+            };
+
+            self.add(Route {
+                method: route.method,
+                pattern,
+                handler,
+            })
+            .unwrap_or_else(|e| panic!("{e}"));
+        }
+        self
+    }
+
+    fn add(&mut self, route: Route) -> Result<(), String> {
+        if !route.pattern.starts_with('/') {
+            return Err(format!("route must start with /: {}", route.pattern));
+        }
+
+        self.root.insert(
+            &split_path(&route.pattern),
+            route.method.clone(),
+            route.handler.clone(),
+            &route.pattern,
+        )?;
+        self.routes.push(route);
+        Ok(())
+    }
+
+    /// Methods handled by any route
+    fn methods(&self) -> Vec<Method> {
+        let mut methods = vec![];
+        for route in &self.routes {
+            if !methods.contains(&route.method) {
+                methods.push(route.method.clone());
+            }
+        }
+        methods
+    }
+
+    pub async fn handle(&self, mut request: Request) -> anyhow::Result<Response> {
+        // nothing knows about this method, as opposed to this path not supporting it
+        if matches!(request.method(), Method::Extension(_))
+            && !self.methods().contains(request.method())
+        {
+            return Ok(Response::new(Status::NotImplemented));
+        }
+
+        if request.path() == "*" {
+            return Ok(match request.method() {
+                Method::Options => {
+                    let mut response = Response::new(Status::Ok);
+                    let methods = with_implied_methods(self.methods());
+                    response.set_header("Allow", allow_header(&methods));
+                    response
+                }
+                _ => Response::new(Status::BadRequest),
+            });
+        }
+
+        let mut params = vec![];
+        let Some(endpoint) = self.root.find(&split_path(request.path()), &mut params) else {
+            return Ok(Response::new(Status::NotFound));
+        };
+
+        let handler = endpoint
+            .get(request.method())
+            .or_else(|| match request.method() {
+                // the body is left off when the response is written
+                Method::Head => endpoint.get(&Method::Get),
+                _ => None,
+            });
+
+        let Some(handler) = handler else {
+            let status = match request.method() {
+                Method::Options => Status::Ok,
+                _ => Status::MethodNotAllowed,
+            };
+
+            let mut response = Response::new(status);
+            response.set_header("Allow", allow_header(&endpoint.allowed()));
+            return Ok(response);
+        };
+
+        for (name, value) in params {
+            request.set_param(name, value);
+        }
+
+        for state in &self.states {
+            state(request.extensions_mut());
+        }
+
+        handler.call(request).await
+    }
+}
+
+impl Handler for Router {
+    fn call(&self, request: Request) -> BoxFuture<'_, anyhow::Result<Response>> {
+        Box::pin(self.handle(request))
+    }
+}
+
+/// A mounted handler along with the state from the router it came from
+struct WithState {
+    states: Vec<StateFn>,
+    inner: BoxHandler,
+}
+
+impl Handler for WithState {
+    fn call(&self, mut request: Request) -> BoxFuture<'_, anyhow::Result<Response>> {
+        for state in &self.states {
+            state(request.extensions_mut());
+        }
+        self.inner.call(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Version;
+
+    use super::*;
+
+    /// A handler that answers with its name and the parameters it was given in X-Route
+    fn named(name: &'static str, params: &'static [&'static str]) -> impl Handler {
+        move |request: Request| async move {
+            let mut route = name.to_string();
+            for param in params {
+                route.push_str(&format!(" {param}={}", request.param(param).unwrap_or("-")));
+            }
+
+            let mut response = Response::new(Status::Ok);
+            response.set_header("X-Route", route);
+            response
+        }
+    }
+
+    async fn call(router: &Router, method: Method, path: &str) -> Response {
+        let request = Request::new(method, path.to_string(), Version::Http11);
+        router.handle(request).await.unwrap()
+    }
+
+    /// Which route answered, None if it wasn't one of them
+    async fn routed(router: &Router, method: Method, path: &str) -> Option<String> {
+        let response = call(router, method, path).await;
+        response.headers().get("X-Route").map(str::to_string)
+    }
+
+    async fn get(router: &Router, path: &str) -> Option<String> {
+        routed(router, Method::Get, path).await
+    }
+
+    #[tokio::test]
+    async fn literals_over_params_over_wildcards() {
+        let router = Router::new()
+            .get("/users/me", named("me", &[]))
+            .get("/users/:id", named("user", &["id"]))
+            .get("/users/:id/posts", named("posts", &["id"]))
+            .get("/users/*rest", named("rest", &["id", "rest"]));
+
+        assert_eq!(get(&router, "/users/me").await.as_deref(), Some("me"));
+        assert_eq!(
+            get(&router, "/users/42").await.as_deref(),
+            Some("user id=42")
+        );
+        assert_eq!(
+            get(&router, "/users/a%20b").await.as_deref(),
+            Some("user id=a b")
+        );
+        assert_eq!(
+            get(&router, "/users/42/posts").await.as_deref(),
+            Some("posts id=42")
+        );
+
+        // nothing under the literal, so it backtracks to the parameter
+        assert_eq!(
+            get(&router, "/users/me/posts").await.as_deref(),
+            Some("posts id=me")
+        );
+
+        // and then to the wildcard, without the parameter it tried
+        assert_eq!(
+            get(&router, "/users/42/other").await.as_deref(),
+            Some("rest id=- rest=42/other")
+        );
+        assert_eq!(
+            get(&router, "/users/a/b%2Fc").await.as_deref(),
+            Some("rest id=- rest=a/b/c")
+        );
+    }
+
+    #[tokio::test]
+    async fn trailing_slashes() {
+        let router = Router::new()
+            .get("/", named("root", &[]))
+            .get("/about", named("about", &[]))
+            .get("/users/:id", named("user", &["id"]));
+
+        assert_eq!(get(&router, "/").await.as_deref(), Some("root"));
+        assert_eq!(get(&router, "/about").await.as_deref(), Some("about"));
+        assert_eq!(get(&router, "/about/").await.as_deref(), Some("about"));
+        assert_eq!(
+            get(&router, "/users/7/").await.as_deref(),
+            Some("user id=7")
+        );
+        assert_eq!(get(&router, "/about//").await, None);
+        assert_eq!(get(&router, "/users/").await, None);
+    }
+
+    #[tokio::test]
+    async fn not_found_and_method_not_allowed() {
+        let router = Router::new()
+            .get("/items", named("list", &[]))
+            .post("/items", named("create", &[]))
+            .delete("/items/:id", named("delete", &["id"]));
+
+        let response = call(&router, Method::Get, "/nothing").await;
+        assert_eq!(response.status(), Status::NotFound);
+
+        let response = call(&router, Method::Put, "/items").await;
+        assert_eq!(response.status(), Status::MethodNotAllowed);
+        assert_eq!(
+            response.headers().get("Allow"),
+            Some("GET, POST, HEAD, OPTIONS")
+        );
+
+        let response = call(&router, Method::Get, "/items/1").await;
+        assert_eq!(response.status(), Status::MethodNotAllowed);
+        assert_eq!(response.headers().get("Allow"), Some("DELETE, OPTIONS"));
+
+        let response = call(&router, Method::Options, "/items").await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.headers().get("Allow"),
+            Some("GET, POST, HEAD, OPTIONS")
+        );
+    }
+
+    #[tokio::test]
+    async fn head_falls_back_to_get() {
+        let router = Router::new()
+            .get("/a", named("get a", &[]))
+            .get("/b", named("get b", &[]))
+            .route(Method::Head, "/b", named("head b", &[]))
+            .post("/c", named("post c", &[]));
+
+        assert_eq!(
+            routed(&router, Method::Head, "/a").await.as_deref(),
+            Some("get a")
+        );
+        assert_eq!(
+            routed(&router, Method::Head, "/b").await.as_deref(),
+            Some("head b")
+        );
+
+        let response = call(&router, Method::Head, "/c").await;
+        assert_eq!(response.status(), Status::MethodNotAllowed);
+        assert_eq!(response.headers().get("Allow"), Some("POST, OPTIONS"));
+    }
+
+    #[tokio::test]
+    async fn asterisk() {
+        let router = Router::new()
+            .get("/a", named("a", &[]))
+            .put("/b", named("b", &[]));
+
+        let response = call(&router, Method::Options, "*").await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.headers().get("Allow"),
+            Some("GET, PUT, HEAD, OPTIONS")
+        );
+
+        let response = call(&router, Method::Get, "*").await;
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[tokio::test]
+    async fn extension_methods() {
+        let purge = Method::Extension("PURGE".into());
+        let router = Router::new().get("/a", named("a", &[])).route(
+            purge.clone(),
+            "/cache",
+            named("purge", &[]),
+        );
+
+        assert_eq!(
+            routed(&router, purge.clone(), "/cache").await.as_deref(),
+            Some("purge")
+        );
+
+        // routed somewhere, just not here
+        let response = call(&router, purge, "/a").await;
+        assert_eq!(response.status(), Status::MethodNotAllowed);
+        assert_eq!(response.headers().get("Allow"), Some("GET, HEAD, OPTIONS"));
+
+        let response = call(&router, Method::Extension("BREW".into()), "/a").await;
+        assert_eq!(response.status(), Status::NotImplemented);
+    }
+
+    #[tokio::test]
+    async fn mounted_routers_keep_their_state() {
+        let state = |request: Request| async move {
+            let mut response = Response::new(Status::Ok);
+            let state = request
+                .state::<u32>()
+                .map_or("none".into(), |s| s.to_string());
+            response.set_header("X-Route", format!("state {state}"));
+            response
+        };
+
+        let api = Router::new()
+            .get("/", state)
+            .get("/:id", named("item", &["id"]))
+            .with_state(Arc::new(7_u32));
+        let router = Router::new().get("/plain", state).mount("/api/", api);
+
+        assert_eq!(get(&router, "/api").await.as_deref(), Some("state 7"));
+        assert_eq!(get(&router, "/api/").await.as_deref(), Some("state 7"));
+        assert_eq!(get(&router, "/api/3").await.as_deref(), Some("item id=3"));
+        assert_eq!(get(&router, "/plain").await.as_deref(), Some("state none"));
+    }
+
+    #[test]
+    fn invalid_routes() {
+        let handler = || named("x", &[]);
+        let error = |router: Result<Router, String>| router.err().unwrap_or_default();
+
+        assert!(
+            error(Router::new().try_route(Method::Get, "a", handler()))
+                .contains("must start with /")
+        );
+        assert!(
+            error(Router::new().try_route(Method::Get, "/*a/b", handler()))
+                .contains("wildcard must be last")
+        );
+        assert!(
+            error(
+                Router::new()
+                    .get("/:a", handler())
+                    .try_route(Method::Get, "/:b/c", handler())
+            )
+            .contains("conflicting parameter names")
+        );
+        assert!(
+            error(
+                Router::new()
+                    .get("/*a", handler())
+                    .try_route(Method::Post, "/*b", handler())
+            )
+            .contains("conflicting wildcard names")
+        );
+        assert!(
+            error(
+                Router::new()
+                    .get("/a", handler())
+                    .try_route(Method::Get, "/a", handler())
+            )
+            .contains("duplicate route GET /a")
+        );
+    }
+}
@@ -0,0 +1,76 @@
+use std::{io, os::fd::AsRawFd};
+
+use tokio::{
+    fs::File,
+    io::Interest,
+    net::TcpStream,
+    time::{Duration, timeout},
+};
+
+/// The most sendfile(2) moves in one call
+const MAX_SENDFILE: libc::off_t = 0x7fff_f000;
+
+/// Sends `length` bytes of the file from `offset` straight to the socket
+///
+/// The kernel copies from the page cache to the socket, so nothing passes
+/// through userspace. Returns false without having sent anything if the
+/// file can't be sent this way, e.g. its filesystem doesn't support it,
+/// so the caller can fall back to copying it. Each call has to make some
+/// progress within the write timeout, like any other write.
+pub(crate) async fn send_file(
+    socket: &TcpStream,
+    file: &File,
+    offset: u64,
+    length: u64,
+    write_timeout: Duration,
+) -> anyhow::Result<bool> {
+    // offsets that don't fit in an off_t get the buffered copy
+    let (Ok(mut offset), Ok(end)) = (
+        libc::off_t::try_from(offset),
+        libc::off_t::try_from(offset.saturating_add(length)),
+    ) else {
+        return Ok(false);
+    };
+
+    let file = file.as_raw_fd();
+    let mut sent = false;
+    while offset < end {
+        let count = (end - offset).min(MAX_SENDFILE) as usize;
+        let result = timeout(write_timeout, async {
+            loop {
+                socket.writable().await?;
+                let result = socket.try_io(Interest::WRITABLE, || {
+                    // SAFETY: both descriptors stay open for the call and
+                    // the kernel only writes to `offset`
+                    let n = unsafe { libc::sendfile(socket.as_raw_fd(), file, &mut offset, count) };
+                    match n {
+                        n if n < 0 => Err(io::Error::last_os_error()),
+                        n => Ok(n as usize),
+                    }
+                });
+                match result {
+                    Err(e)
+                        if matches!(
+                            e.kind(),
+                            io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+                        ) => {}
+                    result => return result,
+                }
+            }
+        })
+        .await;
+
+        match result {
+            Ok(Ok(0)) => anyhow::bail!("file ended {} bytes early", end - offset),
+            Ok(Ok(_)) => sent = true,
+            Ok(Err(e))
+                if !sent && matches!(e.raw_os_error(), Some(libc::EINVAL | libc::ENOSYS)) =>
+            {
+                return Ok(false);
+            }
+            Ok(Err(e)) => Err(e)?,
+            Err(_) => anyhow::bail!("write timeout"),
+        }
+    }
+    Ok(true)
+}
@@ -0,0 +1,409 @@
+use std::{io, net::SocketAddr, sync::Arc};
+
+use tokio::{
+    net::TcpListener,
+    sync::watch,
+    task::JoinSet,
+    time::{Duration, sleep, timeout},
+};
+use tracing::{debug, info, warn};
+
+use crate::{
+    ConnectionConfig, DRAIN_TIMEOUT, Handler, Middleware, Pipeline, Router,
+    connection::{Service, handle_connection},
+    swap::Swap,
+};
+
+/// The first wait after accept fails for lack of file descriptors or memory
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(10);
+
+/// The longest wait between attempts while accept keeps failing
+const MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// An address to listen on, with its own settings
+///
+/// Anything not set on the listener comes from the ServerBuilder.
+pub struct Listener {
+    addr: String,
+    config: Option<ConnectionConfig>,
+    handler: Option<Arc<dyn Handler>>,
+}
+
+impl Listener {
+    /// e.g. "0.0.0.0:8080" or "[::]:8080"
+    ///
+    /// Port 0 picks a free port, see `Server::local_addrs`.
+    pub fn new(addr: impl Into<String>) -> Self {
+        Self {
+            addr: addr.into(),
+            config: None,
+            handler: None,
+        }
+    }
+
+    pub fn config(mut self, config: ConnectionConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Serves this listener with its own router, e.g. for an admin port
+    pub fn router(self, router: Router) -> Self {
+        self.handler(router)
+    }
+
+    pub fn handler(mut self, handler: impl Handler) -> Self {
+        self.handler = Some(Arc::new(handler));
+        self
+    }
+}
+
+/// Builds a Server
+///
+/// Middleware runs in the order it's added, around the router.
+pub struct ServerBuilder {
+    listeners: Vec<Listener>,
+    pipeline: Pipeline,
+    config: ConnectionConfig,
+    drain_timeout: Duration,
+}
+
+impl ServerBuilder {
+    /// Adds an address to listen on with the default settings
+    pub fn bind(self, addr: impl Into<String>) -> Self {
+        self.listener(Listener::new(addr))
+    }
+
+    pub fn listener(mut self, listener: Listener) -> Self {
+        self.listeners.push(listener);
+        self
+    }
+
+    pub fn router(self, router: Router) -> Self {
+        self.handler(router)
+    }
+
+    /// Serves every request with a single handler instead of a router
+    pub fn handler(mut self, handler: impl Handler) -> Self {
+        self.pipeline = self.pipeline.handler(handler);
+        self
+    }
+
+    pub fn layer(mut self, middleware: impl Middleware) -> Self {
+        self.pipeline = self.pipeline.layer(middleware);
+        self
+    }
+
+    /// The connection settings for listeners that don't have their own
+    pub fn config(mut self, config: ConnectionConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// How long open connections get to finish after a shutdown
+    pub fn drain_timeout(mut self, drain_timeout: Duration) -> Self {
+        self.drain_timeout = drain_timeout;
+        self
+    }
+
+    /// Binds the listening sockets
+    pub async fn build(self) -> anyhow::Result<Server> {
+        let drain_timeout = self.drain_timeout;
+
+        let mut listeners = Vec::new();
+        for (addr, service) in self.services()? {
+            let socket = TcpListener::bind(&addr)
+                .await
+                .map_err(|e| anyhow::anyhow!("can't listen on {addr}: {e}"))?;
+
+            listeners.push(Bound {
+                socket,
+                addr,
+                service: Arc::new(Swap::new(service)),
+            });
+        }
+
+        let services = listeners
+            .iter()
+            .map(|listener| (listener.addr.clone(), listener.service.clone()))
+            .collect();
+        let (shutdown, _) = watch::channel(false);
+
+        Ok(Server {
+            listeners,
+            shared: Arc::new(Shared {
+                services,
+                drain_timeout: Swap::new(drain_timeout),
+                shutdown,
+            }),
+        })
+    }
+
+    /// What each listener's address will be served with
+    fn services(self) -> anyhow::Result<Vec<(String, Service)>> {
+        if self.listeners.is_empty() {
+            anyhow::bail!("no address to listen on");
+        }
+
+        let services = self.listeners.into_iter().map(|listener| {
+            // the middleware wraps listeners' own handlers too
+            let handler = match listener.handler {
+                Some(handler) => self.pipeline.clone().handler(handler).build(),
+                None => self.pipeline.clone().build(),
+            };
+
+            let service = Service {
+                handler,
+                config: listener.config.unwrap_or(self.config),
+            };
+            (listener.addr, service)
+        });
+        Ok(services.collect())
+    }
+
+    /// Binds the listening sockets and serves connections until an error
+    pub async fn serve(self) -> anyhow::Result<()> {
+        self.build().await?.serve().await
+    }
+}
+
+/// An HTTP server listening on one or more sockets
+pub struct Server {
+    listeners: Vec<Bound>,
+    shared: Arc<Shared>,
+}
+
+struct Bound {
+    socket: TcpListener,
+
+    /// The address as it was given, to match it up on reload
+    addr: String,
+
+    service: Arc<Swap<Service>>,
+}
+
+/// The parts of a running Server that a ServerHandle can change
+struct Shared {
+    services: Vec<(String, Arc<Swap<Service>>)>,
+    drain_timeout: Swap<Duration>,
+    shutdown: watch::Sender<bool>,
+}
+
+impl Server {
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder {
+            listeners: Vec::new(),
+            pipeline: Pipeline::new(Router::new()),
+            config: ConnectionConfig::default(),
+            drain_timeout: DRAIN_TIMEOUT,
+        }
+    }
+
+    /// The addresses being listened on, in the order they were added
+    pub fn local_addrs(&self) -> anyhow::Result<Vec<SocketAddr>> {
+        self.listeners
+            .iter()
+            .map(|listener| Ok(listener.socket.local_addr()?))
+            .collect()
+    }
+
+    /// A handle for changing what's served, or shutting down, once the server is running
+    pub fn handle(&self) -> ServerHandle {
+        ServerHandle {
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// Accepts connections on every listener, handling each one on its own task
+    ///
+    /// Returns once the server has been shut down through a ServerHandle
+    /// and its connections have finished, or on an error.
+    pub async fn serve(self) -> anyhow::Result<()> {
+        let mut tasks = JoinSet::new();
+        for listener in self.listeners {
+            info!("listening at {}", listener.socket.local_addr()?);
+            tasks.spawn(accept_loop(listener, self.shared.clone()));
+        }
+
+        // an error stops everything, the other listeners are aborted on return
+        while let Some(result) = tasks.join_next().await {
+            result??;
+        }
+        Ok(())
+    }
+}
+
+async fn accept_loop(listener: Bound, shared: Arc<Shared>) -> anyhow::Result<()> {
+    let mut shutdown = shared.shutdown.subscribe();
+    let mut connections = JoinSet::new();
+    let mut backoff = ACCEPT_BACKOFF;
+
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.socket.accept() => accepted,
+            _ = shutdown.wait_for(|&shutdown| shutdown) => break,
+        };
+
+        let (stream, addr) = match accepted {
+            Ok(accepted) => {
+                backoff = ACCEPT_BACKOFF;
+                accepted
+            }
+            Err(e) => match AcceptError::classify(&e) {
+                AcceptError::Connection => {
+                    debug!("accept on {} failed: {e}", listener.addr);
+                    continue;
+                }
+                AcceptError::Resources => {
+                    // the connection stays in the backlog until there's room for it
+                    warn!(
+                        "accept on {} failed: {e}, retrying in {backoff:?}",
+                        listener.addr
+                    );
+                    tokio::select! {
+                        _ = sleep(backoff) => (),
+                        _ = shutdown.wait_for(|&shutdown| shutdown) => break,
+                    }
+                    backoff = (backoff * 2).min(MAX_ACCEPT_BACKOFF);
+                    continue;
+                }
+                AcceptError::Fatal => {
+                    anyhow::bail!("accept on {} failed: {e}", listener.addr);
+                }
+            },
+        };
+        info!("new connection from {addr}");
+
+        // forget about connections that have finished
+        while connections.try_join_next().is_some() {}
+
+        let service = listener.service.clone();
+        let shutdown = shutdown.clone();
+        connections.spawn(async move {
+            match handle_connection(stream, service, shutdown).await {
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("connection from {addr} failed: {e}");
+                }
+            }
+        });
+    }
+
+    // stop accepting straight away rather than when the connections are done
+    let local_addr = listener.socket.local_addr()?;
+    drop(listener.socket);
+
+    let drain_timeout = *shared.drain_timeout.load();
+    let drained = timeout(drain_timeout, async {
+        while connections.join_next().await.is_some() {}
+    })
+    .await;
+
+    if drained.is_err() {
+        warn!(
+            "closing {} connections to {local_addr} still open after {drain_timeout:?}",
+            connections.len()
+        );
+        connections.shutdown().await;
+    }
+    Ok(())
+}
+
+/// How bad an error from accept is
+enum AcceptError {
+    /// Only the connection being accepted is lost, e.g. it was reset while queued
+    Connection,
+
+    /// Out of file descriptors or memory, which can clear up after a while
+    Resources,
+
+    /// The listening socket itself is broken
+    Fatal,
+}
+
+impl AcceptError {
+    fn classify(e: &io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::TimedOut => return Self::Connection,
+            io::ErrorKind::OutOfMemory => return Self::Resources,
+            _ => (),
+        }
+
+        #[cfg(unix)]
+        if let Some(errno) = e.raw_os_error() {
+            match errno {
+                libc::EMFILE | libc::ENFILE | libc::ENOMEM | libc::ENOBUFS => {
+                    return Self::Resources;
+                }
+                // network errors on the new socket that accept(2) passes on
+                libc::EPROTO
+                | libc::ENOPROTOOPT
+                | libc::ENETDOWN
+                | libc::ENETUNREACH
+                | libc::EHOSTDOWN
+                | libc::EHOSTUNREACH
+                | libc::EOPNOTSUPP => return Self::Connection,
+                #[cfg(target_os = "linux")]
+                libc::ENONET => return Self::Connection,
+                _ => (),
+            }
+        }
+
+        Self::Fatal
+    }
+}
+
+/// Swaps in new handlers and settings on a running Server, or shuts it down
+#[derive(Clone)]
+pub struct ServerHandle {
+    shared: Arc<Shared>,
+}
+
+impl ServerHandle {
+    /// Replaces the router, middleware and settings with those from the builder
+    ///
+    /// Requests already being handled finish on the old ones. The builder
+    /// has to have the same listener addresses as the running server since
+    /// sockets aren't rebound, otherwise nothing is changed.
+    pub fn reload(&self, builder: ServerBuilder) -> anyhow::Result<()> {
+        let drain_timeout = builder.drain_timeout;
+        let mut services = builder.services()?;
+        let current = &self.shared.services;
+
+        if let Some((addr, _)) = services
+            .iter()
+            .find(|(addr, _)| !current.iter().any(|(a, _)| a == addr))
+        {
+            anyhow::bail!("can't add listener {addr} without a restart");
+        }
+        if let Some((addr, _)) = current
+            .iter()
+            .find(|(addr, _)| !services.iter().any(|(a, _)| a == addr))
+        {
+            anyhow::bail!("can't remove listener {addr} without a restart");
+        }
+
+        for (addr, service) in current {
+            let idx = services
+                .iter()
+                .position(|(a, _)| a == addr)
+                .expect("checked above");
+            service.store(services.swap_remove(idx).1);
+        }
+        self.shared.drain_timeout.store(drain_timeout);
+        Ok(())
+    }
+
+    /// Stops accepting connections and closes the open ones once they're idle
+    ///
+    /// Responses already on their way out are sent with Connection: close,
+    /// anything still going after the drain timeout is cut off. `serve`
+    /// returns when it's all done.
+    pub fn shutdown(&self) {
+        self.shared.shutdown.send_replace(true);
+    }
+}
@@ -0,0 +1,643 @@
+use std::{
+    borrow::Cow,
+    fs::Metadata,
+    hash::{BuildHasher, Hasher, RandomState},
+    io::{self, SeekFrom},
+    ops::Range,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use tokio::{
+    fs::{self, File},
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt},
+};
+
+use crate::{
+    Body, BoxFuture, Handler, Method, Preconditions, Request, Response, Status, WRITE_CHUNK_SIZE,
+    allow_header, autoindex, conditional, date, mime,
+    range::{self, Ranges},
+    uri,
+};
+
+/// Which symlinks under the root a StaticFiles handler will serve through
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, strum::Display, strum::EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum Symlinks {
+    /// Any symlink, wherever it points
+    Follow,
+
+    /// Symlinks that point somewhere under the root
+    #[default]
+    WithinRoot,
+
+    /// None at all, even ones that stay under the root
+    Deny,
+}
+
+/// Serves the files under a directory
+///
+/// The request path, after the prefix, is percent-decoded and has its dot
+/// segments removed before it's looked up under the root, so nothing
+/// outside it can be reached. A directory is served by its index file, and
+/// asking for one without the trailing slash redirects to it so relative
+/// links work. Directories without an index file can be listed instead,
+/// see `autoindex`.
+///
+/// GET requests can ask for parts of a file with Range, see `file_response`.
+///
+/// ```no_run
+/// # use webserver::{Router, StaticFiles};
+/// let router = Router::new().get("/assets/*file", StaticFiles::new("public").prefix("/assets"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct StaticFiles {
+    root: PathBuf,
+
+    /// The root with its symlinks resolved, if it existed when we were made
+    canonical: Option<PathBuf>,
+
+    prefix: String,
+    symlinks: Symlinks,
+    index: Option<String>,
+    autoindex: bool,
+    sendfile: bool,
+}
+
+impl StaticFiles {
+    /// The root is canonicalized once here rather than on every request
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        Self {
+            canonical: std::fs::canonicalize(&root).ok(),
+            root,
+            prefix: String::new(),
+            symlinks: Symlinks::default(),
+            index: Some("index.html".to_string()),
+            autoindex: false,
+            sendfile: true,
+        }
+    }
+
+    /// The part of the path to drop before looking it up, e.g. where it's routed
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn symlinks(mut self, symlinks: Symlinks) -> Self {
+        self.symlinks = symlinks;
+        self
+    }
+
+    /// The file that serves a directory, None to not serve directories
+    pub fn index(mut self, index: Option<String>) -> Self {
+        self.index = index;
+        self
+    }
+
+    /// Lists the contents of directories that don't have an index file
+    ///
+    /// The listing is an HTML page, or JSON for clients that prefer
+    /// `application/json`, and can be sorted with `?sort=name|size|modified`
+    /// and `&order=asc|desc`. Hidden files and anything the symlink policy
+    /// rules out are left off.
+    pub fn autoindex(mut self, autoindex: bool) -> Self {
+        self.autoindex = autoindex;
+        self
+    }
+
+    /// Whether files and single ranges are sent as `Body::File`, on by default
+    ///
+    /// Off, they're read through a buffer like any other body, which is
+    /// mostly useful for comparing the two.
+    pub fn sendfile(mut self, sendfile: bool) -> Self {
+        self.sendfile = sendfile;
+        self
+    }
+
+    async fn serve(&self, request: Request) -> anyhow::Result<Response> {
+        if !matches!(request.method(), Method::Get | Method::Head) {
+            let mut response = Response::new(Status::MethodNotAllowed);
+            response.set_header(
+                "Allow",
+                allow_header(&[Method::Get, Method::Head, Method::Options]),
+            );
+            return Ok(response);
+        }
+
+        let Some(rest) = request
+            .path()
+            .strip_prefix(self.prefix.as_str())
+            .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+        else {
+            return Ok(Response::new(Status::NotFound));
+        };
+        let Some(relative) = normalize(rest) else {
+            return Ok(Response::new(Status::NotFound));
+        };
+
+        let root = match &self.canonical {
+            Some(root) => Cow::Borrowed(root.as_path()),
+            // it may have been created since
+            None => Cow::Owned(fs::canonicalize(&self.root).await.map_err(|e| {
+                anyhow::anyhow!("can't open document root {}: {e}", self.root.display())
+            })?),
+        };
+
+        let mut path = match self.resolve(&root, &relative).await {
+            Ok(Some(path)) => path,
+            Ok(None) => return Ok(Response::new(Status::NotFound)),
+            Err(e) => return error_response(e),
+        };
+        let mut name = relative;
+
+        let metadata = match fs::metadata(&path).await {
+            Ok(metadata) => metadata,
+            Err(e) => return error_response(e),
+        };
+
+        if metadata.is_dir() {
+            // relative links in the index are resolved against the directory
+            if !rest.ends_with('/') {
+                let mut location = self.location(&name);
+                if let Some(query) = request.query() {
+                    location.push('?');
+                    location.push_str(query);
+                }
+
+                let mut response = Response::new(Status::MovedPermanently);
+                response.set_header("Location", location);
+                return Ok(response);
+            }
+
+            let index = match &self.index {
+                Some(index) => match self.resolve(&root, &name.join(index)).await {
+                    Ok(found) => found.map(|path| (path, name.join(index))),
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                    Err(e) => return error_response(e),
+                },
+                None => None,
+            };
+
+            match index {
+                Some((index_path, index_name)) => {
+                    path = index_path;
+                    name = index_name;
+                }
+                None if self.autoindex => return self.listing(&request, &root, &name, &path).await,
+                None => return Ok(Response::new(Status::NotFound)),
+            }
+        } else if rest.ends_with('/') {
+            return Ok(Response::new(Status::NotFound));
+        }
+
+        let file = match File::open(&path).await {
+            Ok(file) => file,
+            Err(e) => return error_response(e),
+        };
+        let metadata = file.metadata().await?;
+        if !metadata.is_file() {
+            return Ok(Response::new(Status::NotFound));
+        }
+
+        // the name that was asked for, a symlink's target may not have the same extension
+        file_response(
+            &request,
+            file,
+            &metadata,
+            mime::from_path(&name),
+            self.sendfile,
+        )
+        .await
+    }
+
+    /// Where a directory is, with the trailing slash
+    ///
+    /// Made from the normalized path rather than the one asked for, which
+    /// could start with `//` and send the client to another host.
+    fn location(&self, relative: &Path) -> String {
+        let mut location = self.prefix.clone();
+        for segment in relative.iter() {
+            location.push('/');
+            location.push_str(&uri::percent_encode(&segment.to_string_lossy()));
+        }
+        location.push('/');
+        location
+    }
+
+    /// Answers with the contents of a directory
+    async fn listing(
+        &self,
+        request: &Request,
+        root: &Path,
+        relative: &Path,
+        dir: &Path,
+    ) -> anyhow::Result<Response> {
+        let mut read_dir = match fs::read_dir(dir).await {
+            Ok(read_dir) => read_dir,
+            Err(e) => return error_response(e),
+        };
+
+        let mut entries = Vec::new();
+        while let Some(entry) = read_dir.next_entry().await? {
+            // a name that isn't UTF-8 couldn't be asked for anyway
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            if name.starts_with('.') {
+                continue;
+            }
+
+            let Ok(Some(path)) = self.resolve(root, &relative.join(&name)).await else {
+                continue;
+            };
+            let Ok(metadata) = fs::metadata(&path).await else {
+                continue;
+            };
+
+            entries.push(autoindex::Entry {
+                name,
+                is_dir: metadata.is_dir(),
+                size: metadata.len(),
+                modified: metadata.modified().ok(),
+            });
+        }
+
+        let sort = autoindex::Sort::from_query(request.query());
+        sort.apply(&mut entries);
+
+        let path =
+            uri::percent_decode(request.path()).unwrap_or_else(|| request.path().to_string());
+        let mut response = Response::new(Status::Ok);
+        if autoindex::prefers_json(request.header("Accept")) {
+            response.set_header("Content-Type", "application/json");
+            response.set_body(autoindex::json(&path, &entries));
+        } else {
+            let parent = relative.components().next().is_some();
+            response.set_header("Content-Type", "text/html; charset=utf-8");
+            response.set_body(autoindex::html(&path, &entries, sort, parent));
+        }
+        response.set_header("Vary", "Accept");
+        Ok(response)
+    }
+
+    /// Finds the real path of a file under the root, None if the symlink policy rules it out
+    ///
+    /// The resolved path is what gets opened, so a symlink that's changed
+    /// after it was checked isn't followed.
+    async fn resolve(&self, root: &Path, relative: &Path) -> io::Result<Option<PathBuf>> {
+        let joined = root.join(relative);
+        let resolved = fs::canonicalize(&joined).await?;
+
+        let allowed = match self.symlinks {
+            Symlinks::Follow => true,
+            Symlinks::WithinRoot => resolved.starts_with(root),
+            // with the root already canonical, any difference is down to a symlink
+            Symlinks::Deny => resolved == joined,
+        };
+        Ok(allowed.then_some(resolved))
+    }
+}
+
+impl Handler for StaticFiles {
+    fn call(&self, request: Request) -> BoxFuture<'_, anyhow::Result<Response>> {
+        Box::pin(self.serve(request))
+    }
+}
+
+/// Answers with the whole file, or the parts of it the Range header asks for
+///
+/// The conditional headers are checked first, against an ETag made from
+/// the file's size and modification time, and can turn it into a 304 or
+/// 412. After that one range gets a 206 with just those bytes, several get
+/// a 206 with a multipart/byteranges body, and ranges that are all past
+/// the end get a 416. If-Range only lets the Range through if the file
+/// hasn't changed since the client got the rest of it.
+async fn file_response(
+    request: &Request,
+    mut file: File,
+    metadata: &Metadata,
+    content_type: &'static str,
+    sendfile: bool,
+) -> anyhow::Result<Response> {
+    let length = metadata.len();
+    let modified = metadata.modified().ok();
+    let last_modified = modified.map(date::format_http_date);
+    let etag = modified.map(|modified| etag(length, modified));
+
+    // Range only means something for GET, a HEAD gets the headers of the whole file
+    let ranges = match request.header("Range") {
+        Some(value)
+            if request.method() == &Method::Get
+                && if_range_holds(request, etag.as_deref(), modified, last_modified.as_deref()) =>
+        {
+            range::parse_range(value, length)
+        }
+        _ => Ranges::Ignore,
+    };
+
+    let preconditions = Preconditions::from_request(request);
+    let unmet = preconditions.evaluate(etag.as_deref(), modified);
+
+    let mut response = match ranges {
+        _ if let Some(status) = unmet => Response::new(status),
+        Ranges::Ignore => {
+            let mut response = Response::new(Status::Ok);
+            response.set_header("Content-Type", content_type);
+            response.set_body(if sendfile {
+                Body::from_file(file, 0, length)
+            } else {
+                Body::from_reader(file, Some(length))
+            });
+            response
+        }
+        Ranges::Unsatisfiable => {
+            let mut response = Response::new(Status::RangeNotSatisfiable);
+            response.set_header("Content-Range", format!("bytes */{length}"));
+            response
+        }
+        Ranges::Satisfiable(ranges) if ranges.len() == 1 => {
+            let range = &ranges[0];
+            let part = range.end - range.start;
+            let body = if sendfile {
+                Body::from_file(file, range.start, part)
+            } else {
+                file.seek(SeekFrom::Start(range.start)).await?;
+                Body::from_reader(file.take(part), Some(part))
+            };
+
+            let mut response = Response::new(Status::PartialContent);
+            response.set_header("Content-Type", content_type);
+            response.set_header("Content-Range", range::content_range(range, length));
+            response.set_body(body);
+            response
+        }
+        Ranges::Satisfiable(ranges) => {
+            let boundary = format!("{:016x}", RandomState::new().build_hasher().finish());
+            let mut response = Response::new(Status::PartialContent);
+            response.set_header(
+                "Content-Type",
+                format!("multipart/byteranges; boundary={boundary}"),
+            );
+            response.set_body(multipart_body(
+                file,
+                ranges,
+                length,
+                content_type,
+                &boundary,
+            ));
+            response
+        }
+    };
+
+    response.set_header("Accept-Ranges", "bytes");
+    if let Some(etag) = etag {
+        response.set_header("ETag", etag);
+    }
+    if let Some(last_modified) = last_modified {
+        response.set_header("Last-Modified", last_modified);
+    }
+    Ok(response)
+}
+
+/// An ETag from a file's size and modification time
+///
+/// It's weak while the file is less than a second old, since it could be
+/// changed again without the time moving on.
+fn etag(length: u64, modified: SystemTime) -> String {
+    let since_epoch = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let tag = format!(
+        "\"{:x}.{:x}-{length:x}\"",
+        since_epoch.as_secs(),
+        since_epoch.subsec_nanos()
+    );
+    if is_settled(modified) {
+        tag
+    } else {
+        format!("W/{tag}")
+    }
+}
+
+/// Whether a file was last changed long enough ago for its validators to be strong
+fn is_settled(modified: SystemTime) -> bool {
+    SystemTime::now()
+        .duration_since(modified)
+        .is_ok_and(|age| age >= Duration::from_secs(1))
+}
+
+/// Whether a Range should be honoured given any If-Range (RFC 9110 section 13.1.5)
+///
+/// An entity-tag has to be a strong match for the ETag. A date has to
+/// match Last-Modified exactly, and only counts if the file is settled.
+fn if_range_holds(
+    request: &Request,
+    etag: Option<&str>,
+    modified: Option<SystemTime>,
+    last_modified: Option<&str>,
+) -> bool {
+    let Some(if_range) = request.header("If-Range").map(str::trim) else {
+        return true;
+    };
+
+    if if_range.starts_with('"') || if_range.starts_with("W/") {
+        return conditional::if_range_matches(if_range, etag);
+    }
+    modified.is_some_and(is_settled) && last_modified == Some(if_range)
+}
+
+/// A multipart/byteranges body with a part for each range (RFC 9110 section 14.6)
+///
+/// The parts are read from the file on another task as the body is sent,
+/// the length is worked out up front so it can still have a Content-Length.
+fn multipart_body(
+    file: File,
+    ranges: Vec<Range<u64>>,
+    length: u64,
+    content_type: &str,
+    boundary: &str,
+) -> Body {
+    let parts: Vec<(String, Range<u64>)> = ranges
+        .into_iter()
+        .map(|range| {
+            let head = format!(
+                "\r\n--{boundary}\r\nContent-Type: {content_type}\r\nContent-Range: {}\r\n\r\n",
+                range::content_range(&range, length)
+            );
+            (head, range)
+        })
+        .collect();
+    let end = format!("\r\n--{boundary}--\r\n");
+
+    let total = parts
+        .iter()
+        .map(|(head, range)| head.len() as u64 + range.end - range.start)
+        .sum::<u64>()
+        + end.len() as u64;
+
+    let (reader, writer) = tokio::io::simplex(WRITE_CHUNK_SIZE);
+    tokio::spawn(async move {
+        // if this fails the body comes up short, which closes the connection
+        let _ = write_parts(file, parts, end, writer).await;
+    });
+
+    Body::from_reader(reader, Some(total))
+}
+
+async fn write_parts(
+    mut file: File,
+    parts: Vec<(String, Range<u64>)>,
+    end: String,
+    mut writer: impl AsyncWrite + Unpin,
+) -> io::Result<()> {
+    for (head, range) in parts {
+        writer.write_all(head.as_bytes()).await?;
+        file.seek(SeekFrom::Start(range.start)).await?;
+        let mut part = (&mut file).take(range.end - range.start);
+        tokio::io::copy(&mut part, &mut writer).await?;
+    }
+    writer.write_all(end.as_bytes()).await?;
+
+    // dropping the writer alone doesn't end a simplex stream
+    writer.shutdown().await
+}
+
+/// Turns a request path into a path relative to the root (RFC 3986 section 5.2.4)
+///
+/// Each segment is decoded on its own, so an encoded slash can't make a
+/// new one, and ".." never goes above the root. None if a segment can't be
+/// decoded or isn't something a file could be called.
+fn normalize(path: &str) -> Option<PathBuf> {
+    let mut segments = Vec::new();
+    for segment in path.split('/') {
+        let segment = uri::percent_decode(segment)?;
+        match segment.as_str() {
+            "" | "." => (),
+            ".." => {
+                segments.pop();
+            }
+            s if s.contains(['/', '\\', '\0']) => return None,
+            // a drive letter would replace the whole path when it's joined
+            s if cfg!(windows) && s.contains(':') => return None,
+            _ => segments.push(segment),
+        }
+    }
+    Some(segments.iter().collect())
+}
+
+/// Answers for a file that couldn't be opened
+///
+/// Missing files are a 404 and unreadable ones a 403, anything else is an
+/// error with the server.
+fn error_response(e: io::Error) -> anyhow::Result<Response> {
+    match e.kind() {
+        io::ErrorKind::NotFound | io::ErrorKind::NotADirectory => {
+            Ok(Response::new(Status::NotFound))
+        }
+        io::ErrorKind::PermissionDenied => Ok(Response::new(Status::Forbidden)),
+        _ => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Version;
+
+    use super::*;
+
+    fn normalized(path: &str) -> Option<String> {
+        normalize(path).map(|path| path.to_string_lossy().into_owned())
+    }
+
+    #[test]
+    fn normalize_stays_under_the_root() {
+        assert_eq!(normalized("/a/b.txt").as_deref(), Some("a/b.txt"));
+        assert_eq!(normalized("//a/./b//").as_deref(), Some("a/b"));
+        assert_eq!(normalized("/a/../b").as_deref(), Some("b"));
+        assert_eq!(
+            normalized("/../../etc/passwd").as_deref(),
+            Some("etc/passwd")
+        );
+        assert_eq!(normalized("/a/%2e%2e/%2E%2E/b").as_deref(), Some("b"));
+        assert_eq!(normalized("/a%20b").as_deref(), Some("a b"));
+        assert_eq!(normalized("").as_deref(), Some(""));
+    }
+
+    #[test]
+    fn normalize_rejects_what_a_file_couldnt_be_called() {
+        assert_eq!(normalized("/a%2Fb"), None);
+        assert_eq!(normalized("/..%2fetc"), None);
+        assert_eq!(normalized("/a%5Cb"), None);
+        assert_eq!(normalized("/a%00.txt"), None);
+        assert_eq!(normalized("/a%2"), None);
+        assert_eq!(normalized("/a%zz"), None);
+        assert_eq!(normalized("/%ff"), None);
+    }
+
+    /// A root with a `sub` and an `a b` directory in it, removed on drop
+    struct Root(PathBuf);
+
+    impl Root {
+        fn new(name: &str) -> Self {
+            let root =
+                std::env::temp_dir().join(format!("webserver-test-{name}-{}", std::process::id()));
+            std::fs::create_dir_all(root.join("sub")).unwrap();
+            std::fs::create_dir_all(root.join("a b")).unwrap();
+            Self(root)
+        }
+    }
+
+    impl Drop for Root {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    async fn location(files: &StaticFiles, path: &str) -> Option<String> {
+        let request = Request::new(Method::Get, path.to_string(), Version::Http11);
+        let response = files.serve(request).await.unwrap();
+        assert_eq!(response.status(), Status::MovedPermanently, "{path}");
+        response.headers().get("Location").map(str::to_string)
+    }
+
+    #[tokio::test]
+    async fn directories_redirect_to_the_trailing_slash() {
+        let root = Root::new("redirect");
+        let files = StaticFiles::new(&root.0);
+        assert_eq!(location(&files, "/sub").await.as_deref(), Some("/sub/"));
+        assert_eq!(location(&files, "/a%20b").await.as_deref(), Some("/a%20b/"));
+        assert_eq!(
+            location(&files, "/x/../sub").await.as_deref(),
+            Some("/sub/")
+        );
+
+        let files = StaticFiles::new(&root.0).prefix("/assets/");
+        assert_eq!(
+            location(&files, "/assets").await.as_deref(),
+            Some("/assets/")
+        );
+        assert_eq!(
+            location(&files, "/assets/sub").await.as_deref(),
+            Some("/assets/sub/")
+        );
+    }
+
+    #[tokio::test]
+    async fn redirects_stay_on_this_host() {
+        let root = Root::new("open-redirect");
+        let files = StaticFiles::new(&root.0);
+        assert_eq!(
+            location(&files, "//evil.example/..").await.as_deref(),
+            Some("/")
+        );
+        assert_eq!(
+            location(&files, "//evil.example/../sub").await.as_deref(),
+            Some("/sub/")
+        );
+
+        // an encoded slash doesn't make a segment, so there's nothing to redirect to
+        let request = Request::new(Method::Get, "/%2fevil.example/..".into(), Version::Http11);
+        let response = files.serve(request).await.unwrap();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+}
@@ -0,0 +1,195 @@
+macro_rules! statuses {
+    ($($(#[$meta:meta])* $name:ident = $code:literal, $reason:literal;)+) => {
+        /// Response status codes
+        ///
+        /// Covers the IANA HTTP Status Code Registry,
+        /// anything else can be sent with Custom.
+        ///
+        /// Statuses compare by code, so `Custom(404)` is the same as
+        /// `NotFound`. Use `from_code` to get the named variant.
+        #[derive(Debug, Copy, Clone)]
+        #[allow(clippy::enum_variant_names)] // MultiStatus
+        pub enum Status {
+            $($(#[$meta])* $name,)+
+
+            /// A code without a registered reason phrase
+            Custom(u16),
+        }
+
+        impl Status {
+            pub fn code(&self) -> u16 {
+                match self {
+                    $(Status::$name => $code,)+
+                    Status::Custom(code) => *code,
+                }
+            }
+
+            /// The canonical reason phrase, if the code is registered
+            pub fn reason(&self) -> Option<&'static str> {
+                match self {
+                    $(Status::$name => Some($reason),)+
+                    Status::Custom(_) => None,
+                }
+            }
+
+            /// Looks up a registered status, falling back to Custom
+            ///
+            /// None if the code isn't three digits (RFC 9112 section 4).
+            pub fn from_code(code: u16) -> Option<Self> {
+                match code {
+                    $($code => Some(Status::$name),)+
+                    100..=999 => Some(Status::Custom(code)),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+statuses! {
+    Continue = 100, "Continue";
+    SwitchingProtocols = 101, "Switching Protocols";
+    Processing = 102, "Processing";
+    EarlyHints = 103, "Early Hints";
+
+    Ok = 200, "OK";
+    Created = 201, "Created";
+    Accepted = 202, "Accepted";
+    NonAuthoritativeInformation = 203, "Non-Authoritative Information";
+    NoContent = 204, "No Content";
+    ResetContent = 205, "Reset Content";
+    PartialContent = 206, "Partial Content";
+    MultiStatus = 207, "Multi-Status";
+    AlreadyReported = 208, "Already Reported";
+    ImUsed = 226, "IM Used";
+
+    MultipleChoices = 300, "Multiple Choices";
+    MovedPermanently = 301, "Moved Permanently";
+    Found = 302, "Found";
+    SeeOther = 303, "See Other";
+    NotModified = 304, "Not Modified";
+    UseProxy = 305, "Use Proxy";
+    TemporaryRedirect = 307, "Temporary Redirect";
+    PermanentRedirect = 308, "Permanent Redirect";
+
+    BadRequest = 400, "Bad Request";
+    Unauthorized = 401, "Unauthorized";
+    PaymentRequired = 402, "Payment Required";
+    Forbidden = 403, "Forbidden";
+    NotFound = 404, "Not Found";
+    MethodNotAllowed = 405, "Method Not Allowed";
+    NotAcceptable = 406, "Not Acceptable";
+    ProxyAuthenticationRequired = 407, "Proxy Authentication Required";
+    RequestTimeout = 408, "Request Timeout";
+    Conflict = 409, "Conflict";
+    Gone = 410, "Gone";
+    LengthRequired = 411, "Length Required";
+    PreconditionFailed = 412, "Precondition Failed";
+    ContentTooLarge = 413, "Content Too Large";
+    UriTooLong = 414, "URI Too Long";
+    UnsupportedMediaType = 415, "Unsupported Media Type";
+    RangeNotSatisfiable = 416, "Range Not Satisfiable";
+    ExpectationFailed = 417, "Expectation Failed";
+    MisdirectedRequest = 421, "Misdirected Request";
+    UnprocessableContent = 422, "Unprocessable Content";
+    Locked = 423, "Locked";
+    FailedDependency = 424, "Failed Dependency";
+    TooEarly = 425, "Too Early";
+    UpgradeRequired = 426, "Upgrade Required";
+    PreconditionRequired = 428, "Precondition Required";
+    TooManyRequests = 429, "Too Many Requests";
+    RequestHeaderFieldsTooLarge = 431, "Request Header Fields Too Large";
+    UnavailableForLegalReasons = 451, "Unavailable For Legal Reasons";
+
+    InternalServerError = 500, "Internal Server Error";
+    NotImplemented = 501, "Not Implemented";
+    BadGateway = 502, "Bad Gateway";
+    ServiceUnavailable = 503, "Service Unavailable";
+    GatewayTimeout = 504, "Gateway Timeout";
+    HttpVersionNotSupported = 505, "HTTP Version Not Supported";
+    VariantAlsoNegotiates = 506, "Variant Also Negotiates";
+    InsufficientStorage = 507, "Insufficient Storage";
+    LoopDetected = 508, "Loop Detected";
+    NotExtended = 510, "Not Extended";
+    NetworkAuthenticationRequired = 511, "Network Authentication Required";
+}
+
+impl Status {
+    pub fn is_informational(&self) -> bool {
+        (100..200).contains(&self.code())
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.code())
+    }
+
+    pub fn is_redirection(&self) -> bool {
+        (300..400).contains(&self.code())
+    }
+
+    pub fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.code())
+    }
+
+    pub fn is_server_error(&self) -> bool {
+        (500..600).contains(&self.code())
+    }
+}
+
+impl PartialEq for Status {
+    fn eq(&self, other: &Self) -> bool {
+        self.code() == other.code()
+    }
+}
+
+impl Eq for Status {}
+
+impl std::hash::Hash for Status {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.code().hash(state);
+    }
+}
+
+impl TryFrom<u16> for Status {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        Status::from_code(value).ok_or_else(|| anyhow::anyhow!("invalid status code: {value}"))
+    }
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.reason() {
+            Some(reason) => write!(f, "{} {}", self.code(), reason),
+            None => write!(f, "{}", self.code()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_code_uses_named_variants() {
+        assert!(matches!(Status::from_code(404), Some(Status::NotFound)));
+        assert!(matches!(Status::from_code(299), Some(Status::Custom(299))));
+    }
+
+    #[test]
+    fn from_code_needs_three_digits() {
+        assert_eq!(Status::from_code(42), None);
+        assert_eq!(Status::from_code(1000), None);
+        assert_eq!(Status::from_code(100), Some(Status::Continue));
+        assert_eq!(Status::from_code(999), Some(Status::Custom(999)));
+        assert!(Status::try_from(99).is_err());
+    }
+
+    #[test]
+    fn compares_by_code() {
+        assert_eq!(Status::Custom(404), Status::NotFound);
+        assert_ne!(Status::Custom(405), Status::NotFound);
+        assert_eq!(Status::Custom(404).reason(), None);
+    }
+}
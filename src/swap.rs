@@ -0,0 +1,28 @@
+use std::sync::{Arc, PoisonError, RwLock};
+
+/// A shared value that can be replaced as a whole while it's in use
+///
+/// Readers get an Arc to whichever version was current when they asked,
+/// so replacing it never changes anything out from under them.
+pub(crate) struct Swap<T> {
+    current: RwLock<Arc<T>>,
+}
+
+impl<T> Swap<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(value)),
+        }
+    }
+
+    pub fn load(&self) -> Arc<T> {
+        self.current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub fn store(&self, value: T) {
+        *self.current.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(value);
+    }
+}
@@ -0,0 +1,417 @@
+//! Just enough TOML for the config file
+//!
+//! Supports comments, `[table]` and `[[array-of-tables]]` headers (one level
+//! deep), bare keys, and strings, integers, booleans and single-line arrays
+//! as values. Everything keeps its line number for error messages.
+
+use crate::ConfigError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    Table(Table),
+    TableArray(Vec<Table>),
+}
+
+impl Value {
+    pub(crate) fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Integer(_) => "integer",
+            Value::Boolean(_) => "boolean",
+            Value::Array(_) => "array",
+            Value::Table(_) => "table",
+            Value::TableArray(_) => "array of tables",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Entry {
+    pub key: String,
+    pub value: Value,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Table {
+    /// Where the table's header is, 0 for the root table
+    pub line: usize,
+    pub entries: Vec<Entry>,
+}
+
+impl Table {
+    fn new(line: usize) -> Self {
+        Self {
+            line,
+            entries: Vec::new(),
+        }
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.key == key)
+    }
+
+    fn get_mut(&mut self, key: &str) -> Option<&mut Entry> {
+        self.entries.iter_mut().find(|entry| entry.key == key)
+    }
+
+    fn insert(&mut self, key: String, value: Value, line: usize) -> Result<(), ConfigError> {
+        if let Some(existing) = self.get(&key) {
+            return Err(ConfigError::new(
+                line,
+                format!("duplicate key {key} (first set on line {})", existing.line),
+            ));
+        }
+
+        self.entries.push(Entry { key, value, line });
+        Ok(())
+    }
+}
+
+/// Which table key/value lines currently go into
+enum Current {
+    Root,
+    Table(String),
+    TableArray(String),
+}
+
+pub(crate) fn parse(src: &str) -> Result<Table, ConfigError> {
+    let mut root = Table::new(0);
+    let mut current = Current::Root;
+
+    for (idx, line) in src.lines().enumerate() {
+        let lineno = idx + 1;
+        let mut cursor = Cursor::new(line, lineno);
+        cursor.skip_whitespace();
+
+        if cursor.at_end() {
+            continue;
+        }
+
+        if cursor.eat("[[") {
+            let name = cursor.key()?;
+            cursor.expect("]]")?;
+            cursor.end()?;
+
+            match root.get_mut(&name) {
+                Some(Entry {
+                    value: Value::TableArray(tables),
+                    ..
+                }) => tables.push(Table::new(lineno)),
+                Some(entry) => {
+                    return Err(ConfigError::new(
+                        lineno,
+                        format!("{name} was already defined on line {}", entry.line),
+                    ));
+                }
+                None => root.insert(
+                    name.clone(),
+                    Value::TableArray(vec![Table::new(lineno)]),
+                    lineno,
+                )?,
+            }
+            current = Current::TableArray(name);
+        } else if cursor.eat("[") {
+            let name = cursor.key()?;
+            cursor.expect("]")?;
+            cursor.end()?;
+
+            root.insert(name.clone(), Value::Table(Table::new(lineno)), lineno)?;
+            current = Current::Table(name);
+        } else {
+            let key = cursor.key()?;
+            cursor.expect("=")?;
+            let value = cursor.value()?;
+            cursor.end()?;
+
+            let table = match &current {
+                Current::Root => &mut root,
+                Current::Table(name) => match root.get_mut(name) {
+                    Some(Entry {
+                        value: Value::Table(table),
+                        ..
+                    }) => table,
+                    _ => unreachable!("current table is always in the root"),
+                },
+                Current::TableArray(name) => match root.get_mut(name) {
+                    Some(Entry {
+                        value: Value::TableArray(tables),
+                        ..
+                    }) => tables.last_mut().expect("array has at least one table"),
+                    _ => unreachable!("current table is always in the root"),
+                },
+            };
+            table.insert(key, value, lineno)?;
+        }
+    }
+
+    Ok(root)
+}
+
+struct Cursor<'a> {
+    rest: &'a str,
+    line: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(src: &'a str, line: usize) -> Self {
+        Self { rest: src, line }
+    }
+
+    fn error(&self, message: impl Into<String>) -> ConfigError {
+        ConfigError::new(self.line, message)
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.rest = self.rest.trim_start_matches([' ', '\t']);
+    }
+
+    /// Whether only whitespace or a comment is left
+    fn at_end(&self) -> bool {
+        self.rest.is_empty() || self.rest.starts_with('#')
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        match self.rest.strip_prefix(token) {
+            Some(rest) => {
+                self.rest = rest;
+                self.skip_whitespace();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), ConfigError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected {token}")))
+        }
+    }
+
+    fn end(&mut self) -> Result<(), ConfigError> {
+        self.skip_whitespace();
+        if self.at_end() {
+            Ok(())
+        } else {
+            Err(self.error(format!("unexpected {}", self.rest)))
+        }
+    }
+
+    fn key(&mut self) -> Result<String, ConfigError> {
+        let len = self
+            .rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+            .unwrap_or(self.rest.len());
+        if len == 0 {
+            return Err(self.error("expected a key"));
+        }
+
+        let key = self.rest[..len].to_string();
+        self.rest = &self.rest[len..];
+        self.skip_whitespace();
+        Ok(key)
+    }
+
+    fn value(&mut self) -> Result<Value, ConfigError> {
+        let value = if self.rest.starts_with('"') {
+            Value::String(self.basic_string()?)
+        } else if self.rest.starts_with('\'') {
+            Value::String(self.literal_string()?)
+        } else if self.eat("[") {
+            let mut values = Vec::new();
+            while !self.eat("]") {
+                values.push(self.value()?);
+                if !self.eat(",") {
+                    self.expect("]")?;
+                    break;
+                }
+            }
+            Value::Array(values)
+        } else {
+            let len = self
+                .rest
+                .find(|c: char| c.is_whitespace() || c == ',' || c == ']' || c == '#')
+                .unwrap_or(self.rest.len());
+            let word = &self.rest[..len];
+            self.rest = &self.rest[len..];
+
+            match word {
+                "true" => Value::Boolean(true),
+                "false" => Value::Boolean(false),
+                "" => return Err(self.error("expected a value")),
+                _ => Value::Integer(
+                    word.replace('_', "")
+                        .parse()
+                        .map_err(|_| self.error(format!("invalid value {word}")))?,
+                ),
+            }
+        };
+
+        self.skip_whitespace();
+        Ok(value)
+    }
+
+    fn basic_string(&mut self) -> Result<String, ConfigError> {
+        let mut chars = self.rest[1..].char_indices();
+        let mut s = String::new();
+        while let Some((idx, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.rest = &self.rest[idx + 2..];
+                    return Ok(s);
+                }
+                '\\' => match chars.next() {
+                    Some((_, 'n')) => s.push('\n'),
+                    Some((_, 't')) => s.push('\t'),
+                    Some((_, 'r')) => s.push('\r'),
+                    Some((_, '"')) => s.push('"'),
+                    Some((_, '\\')) => s.push('\\'),
+                    _ => return Err(self.error("invalid escape in string")),
+                },
+                c => s.push(c),
+            }
+        }
+
+        Err(self.error("unterminated string"))
+    }
+
+    fn literal_string(&mut self) -> Result<String, ConfigError> {
+        match self.rest[1..].find('\'') {
+            Some(end) => {
+                let s = self.rest[1..end + 1].to_string();
+                self.rest = &self.rest[end + 2..];
+                Ok(s)
+            }
+            None => Err(self.error("unterminated string")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(src: &str) -> Value {
+        let root = parse(&format!("key = {src}")).unwrap();
+        root.get("key").unwrap().value.clone()
+    }
+
+    /// The line and message of the error parsing `src`
+    fn error(src: &str) -> (usize, String) {
+        let e = parse(src).unwrap_err();
+        (e.line(), e.to_string())
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(value(r#""hello""#), Value::String("hello".into()));
+        assert_eq!(value(r#""""#), Value::String(String::new()));
+        assert_eq!(
+            value(r#""a\tb\nc\r\"d\" \\ e""#),
+            Value::String("a\tb\nc\r\"d\" \\ e".into())
+        );
+        assert_eq!(
+            value(r##""# not a comment""##),
+            Value::String("# not a comment".into())
+        );
+        assert_eq!(value(r"'C:\path\n'"), Value::String(r"C:\path\n".into()));
+        assert_eq!(value(r#""héllo""#), Value::String("héllo".into()));
+    }
+
+    #[test]
+    fn integers_and_booleans() {
+        assert_eq!(value("42"), Value::Integer(42));
+        assert_eq!(value("-7"), Value::Integer(-7));
+        assert_eq!(value("1_000_000"), Value::Integer(1_000_000));
+        assert_eq!(value("true"), Value::Boolean(true));
+        assert_eq!(value("false"), Value::Boolean(false));
+    }
+
+    #[test]
+    fn arrays() {
+        assert_eq!(value("[]"), Value::Array(Vec::new()));
+        assert_eq!(
+            value(r#"[1, "two", true,]"#),
+            Value::Array(vec![
+                Value::Integer(1),
+                Value::String("two".into()),
+                Value::Boolean(true),
+            ])
+        );
+        assert_eq!(
+            value("[[1], [2, 3]]"),
+            Value::Array(vec![
+                Value::Array(vec![Value::Integer(1)]),
+                Value::Array(vec![Value::Integer(2), Value::Integer(3)]),
+            ])
+        );
+    }
+
+    #[test]
+    fn tables() {
+        let src = "\
+# a comment
+top = 1
+
+[server]   # trailing comment
+name = \"a\"
+
+[[route]]
+path = \"/a\"
+
+[[route]]
+path = \"/b\"
+";
+        let root = parse(src).unwrap();
+        assert_eq!(root.get("top").unwrap().value, Value::Integer(1));
+        assert_eq!(root.get("top").unwrap().line, 2);
+
+        let Value::Table(server) = &root.get("server").unwrap().value else {
+            panic!("server isn't a table");
+        };
+        assert_eq!(server.line, 4);
+        assert_eq!(server.get("name").unwrap().value, Value::String("a".into()));
+
+        let Value::TableArray(routes) = &root.get("route").unwrap().value else {
+            panic!("route isn't an array of tables");
+        };
+        assert_eq!(routes.len(), 2);
+        assert_eq!(routes[0].line, 7);
+        assert_eq!(routes[1].line, 10);
+        assert_eq!(
+            routes[1].get("path").unwrap().value,
+            Value::String("/b".into())
+        );
+    }
+
+    #[test]
+    fn errors_have_line_numbers() {
+        assert_eq!(error("a = 1\n\nb = \"open").0, 3);
+        assert_eq!(error("a = \"\\x\"").0, 1);
+        assert_eq!(error("\n'oops' = 1").0, 2);
+        assert_eq!(error("a 1").0, 1);
+        assert_eq!(error("a =").0, 1);
+        assert_eq!(error("a = 1 2").0, 1);
+        assert_eq!(error("a = nope").0, 1);
+        assert_eq!(error("a = [1, 2").0, 1);
+        assert_eq!(error("[t\na = 1").0, 1);
+        assert_eq!(error("a = 'open").0, 1);
+
+        let (line, message) = error("a = 1\na = 2");
+        assert_eq!(line, 2);
+        assert!(message.contains("first set on line 1"), "{message}");
+
+        let (line, message) = error("[t]\n[[t]]");
+        assert_eq!(line, 2);
+        assert!(message.contains("already defined on line 1"), "{message}");
+
+        assert_eq!(error("[t]\n[t]").0, 2);
+        assert_eq!(error("[[t]]\n[t]").0, 2);
+    }
+}
@@ -0,0 +1,87 @@
+use crate::ParseError;
+
+/// Splits a request-target into its path and query (RFC 9112 section 3.2)
+///
+/// Absolute-form targets have their scheme and authority dropped,
+/// the asterisk-form (OPTIONS *) is kept as a path of "*".
+pub fn split_target(target: &str) -> Result<(String, Option<String>), ParseError> {
+    let target = target.split_once('#').map_or(target, |(target, _)| target);
+
+    let origin = if target == "*" || target.starts_with('/') {
+        target
+    } else if let Some(rest) = target
+        .strip_prefix("http://")
+        .or_else(|| target.strip_prefix("https://"))
+    {
+        match rest.find(['/', '?']) {
+            Some(idx) if rest.as_bytes()[idx] == b'/' => &rest[idx..],
+            Some(idx) => return Ok(("/".to_string(), Some(rest[idx + 1..].to_string()))),
+            None => "/",
+        }
+    } else {
+        return Err(ParseError::BadRequest("invalid request target"));
+    };
+
+    if origin.bytes().any(|c| c.is_ascii_control() || c == b' ') {
+        return Err(ParseError::BadRequest("invalid request target"));
+    }
+
+    Ok(match origin.split_once('?') {
+        Some((path, query)) => (path.to_string(), Some(query.to_string())),
+        None => (origin.to_string(), None),
+    })
+}
+
+/// Decodes %XX escapes, failing on bad escapes or if the result isn't UTF-8
+pub fn percent_decode(s: &str) -> Option<String> {
+    if !s.contains('%') {
+        return Some(s.to_string());
+    }
+
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+    while let Some(c) = iter.next() {
+        if c != b'%' {
+            bytes.push(c);
+            continue;
+        }
+
+        let hi = (iter.next()? as char).to_digit(16)?;
+        let lo = (iter.next()? as char).to_digit(16)?;
+        bytes.push((hi * 16 + lo) as u8);
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+/// Escapes everything but unreserved characters (RFC 3986 section 2.3),
+/// so the result can be used as a single path segment
+pub fn percent_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for c in s.bytes() {
+        if c.is_ascii_alphanumeric() || matches!(c, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(c as char);
+        } else {
+            encoded.push_str(&format!("%{c:02X}"));
+        }
+    }
+    encoded
+}
+
+/// Splits a query string into decoded name and value pairs
+///
+/// `+` is taken as a space, as forms send it. Pairs that can't be decoded
+/// are left as they are.
+pub fn query_pairs(query: &str) -> impl Iterator<Item = (String, String)> + '_ {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let decode = |s: &str| {
+                let s = s.replace('+', " ");
+                percent_decode(&s).unwrap_or(s)
+            };
+            (decode(name), decode(value))
+        })
+}
@@ -0,0 +1,31 @@
+use crate::ParseError;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, strum::Display)]
+pub enum Version {
+    #[strum(serialize = "HTTP/1.0")]
+    Http10,
+
+    #[strum(serialize = "HTTP/1.1")]
+    Http11,
+}
+
+impl TryFrom<&str> for Version {
+    type Error = ParseError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        // HTTP-version = "HTTP/" DIGIT "." DIGIT (RFC 9112 section 2.3)
+        match value.as_bytes() {
+            b"HTTP/1.0" => Ok(Version::Http10),
+            // later 1.x minor versions are backwards compatible with 1.1
+            [b'H', b'T', b'T', b'P', b'/', b'1', b'.', minor] if minor.is_ascii_digit() => {
+                Ok(Version::Http11)
+            }
+            [b'H', b'T', b'T', b'P', b'/', major, b'.', minor]
+                if major.is_ascii_digit() && minor.is_ascii_digit() =>
+            {
+                Err(ParseError::UnsupportedVersion(value.to_string()))
+            }
+            _ => Err(ParseError::BadRequest("invalid version")),
+        }
+    }
+}